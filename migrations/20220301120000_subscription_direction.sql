@@ -0,0 +1,3 @@
+CREATE TYPE direction AS ENUM ('incoming', 'outgoing', 'both');
+
+ALTER TABLE subscriptions ADD COLUMN direction direction NOT NULL DEFAULT 'both';
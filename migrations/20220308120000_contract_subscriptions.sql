@@ -0,0 +1,39 @@
+CREATE TABLE IF NOT EXISTS contract_subscriptions (
+    id serial PRIMARY KEY NOT NULL,
+    user_id bigint NOT NULL,
+    index bigint NOT NULL,
+    subindex bigint NOT NULL,
+    created_at timestamp NOT NULL DEFAULT current_timestamp,
+    UNIQUE (user_id, index, subindex)
+);
+
+CREATE INDEX ON contract_subscriptions (index, subindex);
+
+CREATE OR REPLACE FUNCTION contract_tx_notify()
+RETURNS trigger
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF EXISTS (
+        SELECT true FROM contract_subscriptions
+        WHERE index = NEW.index AND subindex = NEW.subindex LIMIT 1
+    ) THEN
+        PERFORM (
+            WITH summary AS (
+                SELECT summaries.summary FROM summaries WHERE summaries.id = NEW.summary
+            ), payload AS (
+                SELECT concat_ws('|', NEW.id, NEW.index, NEW.subindex, summary) AS payload FROM summary
+            )
+            SELECT pg_notify(TG_ARGV[0], payload) FROM payload
+        );
+    END IF;
+
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER cti_notify_on_insert
+AFTER INSERT
+ON cti
+FOR EACH ROW
+EXECUTE PROCEDURE contract_tx_notify('contract_tx_channel');
@@ -0,0 +1,39 @@
+-- Returns true if a contract logged events in a transaction and all of them are tagged
+-- as CIS-2 token events (transfer, mint, burn, update operator or token metadata).
+CREATE OR REPLACE FUNCTION logs_cis2_events(summary jsonb, contract_index bigint, contract_subindex bigint)
+RETURNS boolean
+LANGUAGE sql
+IMMUTABLE
+AS $$
+    SELECT coalesce(bool_and(log ~* '^f[b-f]'), false)
+    FROM jsonb_array_elements(summary->'Left'->'result'->'events') AS event,
+        jsonb_array_elements_text(event->'events') AS log
+    WHERE event->>'tag' = 'Updated'
+        AND (event->'address'->>'index')::bigint = contract_index
+        AND (event->'address'->>'subindex')::bigint = contract_subindex
+$$;
+
+-- Notify about updates of subscribed contracts and of contracts logging only CIS-2 token events,
+-- which may concern subscribed accounts.
+CREATE OR REPLACE FUNCTION contract_tx_notify()
+RETURNS trigger
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    tx_summary jsonb;
+BEGIN
+    SELECT summaries.summary INTO tx_summary FROM summaries WHERE summaries.id = NEW.summary;
+
+    IF EXISTS (
+        SELECT true FROM contract_subscriptions
+        WHERE index = NEW.index AND subindex = NEW.subindex LIMIT 1
+    ) OR logs_cis2_events(tx_summary, NEW.index, NEW.subindex) THEN
+        PERFORM pg_notify(
+            TG_ARGV[0],
+            concat_ws('|', NEW.id, NEW.index, NEW.subindex, tx_summary)
+        );
+    END IF;
+
+    RETURN NULL;
+END;
+$$;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS scheduled_releases (
+    id bigserial PRIMARY KEY NOT NULL,
+    account bytea NOT NULL,
+    tx_hash text NOT NULL,
+    release_time timestamptz NOT NULL,
+    amount bigint NOT NULL,
+    UNIQUE (account, tx_hash, release_time)
+);
+
+CREATE INDEX ON scheduled_releases (release_time);
@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS chain_update_subscriptions (
+    id serial PRIMARY KEY NOT NULL,
+    user_id bigint NOT NULL UNIQUE,
+    created_at timestamp NOT NULL DEFAULT current_timestamp
+);
+
+-- Chain updates aren't related to any account or contract, so summaries are watched directly.
+CREATE OR REPLACE FUNCTION chain_update_notify()
+RETURNS trigger
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF NEW.summary->'Left'->'type'->>'type' = 'updateTransaction'
+        AND EXISTS (SELECT true FROM chain_update_subscriptions LIMIT 1) THEN
+        PERFORM pg_notify(TG_ARGV[0], concat_ws('|', NEW.id, NEW.summary));
+    END IF;
+
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER summaries_notify_on_insert
+AFTER INSERT
+ON summaries
+FOR EACH ROW
+EXECUTE PROCEDURE chain_update_notify('chain_update_channel');
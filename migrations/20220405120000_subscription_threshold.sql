@@ -0,0 +1,2 @@
+-- Minimum amount in microCCD of transfers to be notified about, NULL for no threshold.
+ALTER TABLE subscriptions ADD COLUMN threshold bigint;
@@ -0,0 +1,3 @@
+-- Disabled rather than enabled categories are stored,
+-- so categories added later are enabled for existing subscriptions.
+ALTER TABLE subscriptions ADD COLUMN disabled_categories text[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS address_book (
+    id serial PRIMARY KEY NOT NULL,
+    user_id bigint NOT NULL,
+    -- Account address or contract address formatted as <index,subindex>
+    address text NOT NULL,
+    name text NOT NULL,
+    created_at timestamp NOT NULL DEFAULT current_timestamp,
+    UNIQUE (user_id, address)
+);
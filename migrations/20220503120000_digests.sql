@@ -0,0 +1,24 @@
+CREATE TYPE delivery_mode AS ENUM ('immediate', 'hourly', 'daily');
+
+CREATE TABLE IF NOT EXISTS users (
+    user_id bigint PRIMARY KEY NOT NULL,
+    delivery_mode delivery_mode NOT NULL DEFAULT 'immediate',
+    -- Offset from UTC of the user's time zone in seconds
+    utc_offset integer NOT NULL DEFAULT 0,
+    -- Local time of daily digests in seconds since midnight
+    digest_time integer NOT NULL DEFAULT 0,
+    last_digest_at timestamptz,
+    created_at timestamp NOT NULL DEFAULT current_timestamp
+);
+
+-- Events accumulated for the next digest
+CREATE TABLE IF NOT EXISTS digest_events (
+    id bigserial PRIMARY KEY NOT NULL,
+    user_id bigint NOT NULL,
+    account bytea NOT NULL,
+    category text NOT NULL,
+    amount bigint,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON digest_events (user_id);
@@ -0,0 +1,17 @@
+CREATE TYPE quiet_mode AS ENUM ('silent', 'defer');
+
+-- Quiet hours in seconds since local midnight, disabled if NULL
+ALTER TABLE users ADD COLUMN quiet_start integer;
+ALTER TABLE users ADD COLUMN quiet_end integer;
+ALTER TABLE users ADD COLUMN quiet_mode quiet_mode NOT NULL DEFAULT 'silent';
+
+-- Messages held until quiet hours end
+CREATE TABLE IF NOT EXISTS deferred_messages (
+    id bigserial PRIMARY KEY NOT NULL,
+    user_id bigint NOT NULL,
+    text text NOT NULL,
+    reply_markup text,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON deferred_messages (user_id);
@@ -0,0 +1,21 @@
+CREATE TYPE delivery_status AS ENUM ('pending', 'sent', 'failed');
+
+-- Messages to be sent to Telegram users, one row per recipient
+CREATE TABLE IF NOT EXISTS outbox (
+    id bigserial PRIMARY KEY NOT NULL,
+    user_id bigint NOT NULL,
+    text text NOT NULL,
+    reply_markup text,
+    -- Critical messages are never sent silently during quiet hours,
+    -- whether other messages are silent is decided when they are sent
+    critical boolean NOT NULL DEFAULT false,
+    status delivery_status NOT NULL DEFAULT 'pending',
+    -- Number of failed attempts to send the message
+    attempts integer NOT NULL DEFAULT 0,
+    next_attempt_at timestamptz NOT NULL DEFAULT now(),
+    last_error text,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    sent_at timestamptz
+);
+
+CREATE INDEX ON outbox (id) WHERE status = 'pending';
@@ -0,0 +1,16 @@
+-- Latest handled transaction index ID for each source: ati, cti and chain_updates
+CREATE TABLE IF NOT EXISTS progress (
+    source text PRIMARY KEY NOT NULL,
+    latest_id bigint NOT NULL
+);
+
+-- Recipients of messages about the latest transactions,
+-- which prevents notifying twice when a transaction is replayed
+CREATE TABLE IF NOT EXISTS handled_messages (
+    source text NOT NULL,
+    index_id bigint NOT NULL,
+    -- Number of the message among messages about the transaction
+    seq integer NOT NULL,
+    user_id bigint NOT NULL,
+    PRIMARY KEY (source, index_id, seq, user_id)
+);
@@ -0,0 +1,2 @@
+-- Direction of transfers, amounts of incoming and outgoing transfers are summed up separately
+ALTER TABLE digest_events ADD COLUMN direction direction;
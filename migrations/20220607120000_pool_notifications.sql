@@ -0,0 +1,41 @@
+-- Index of a subscribed account, which is also ID of the baker pool the account may own
+ALTER TABLE subscriptions ADD COLUMN IF NOT EXISTS account_index bigint;
+
+CREATE INDEX IF NOT EXISTS subscriptions_account_index_idx ON subscriptions (account_index);
+
+-- Current and previous baker pool of each delegator seen in delegation transactions,
+-- which tells which pool a delegator leaves. `NULL` baker ID means passive or no delegation.
+CREATE TABLE IF NOT EXISTS pool_delegators (
+    delegator bytea PRIMARY KEY NOT NULL,
+    baker_id bigint,
+    previous_baker_id bigint,
+    -- Summary which set the current baker pool, a replayed summary keeps the previous one
+    summary_id bigint NOT NULL
+);
+
+-- Pool rewards of subscribed pool owners and all delegation changes,
+-- as any delegator may join or leave a subscribed pool.
+CREATE OR REPLACE FUNCTION pool_notify()
+RETURNS trigger
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF (NEW.summary->'Right'->>'tag' = 'PaydayPoolReward'
+            AND EXISTS (
+                SELECT true FROM subscriptions
+                WHERE account_index = (NEW.summary->'Right'->>'poolOwner')::bigint
+            ))
+        OR (NEW.summary->'Left'->'type'->>'contents' = 'configureDelegation'
+            AND NEW.summary->'Left'->'result'->>'outcome' = 'success') THEN
+        PERFORM pg_notify(TG_ARGV[0], concat_ws('|', NEW.id, NEW.summary));
+    END IF;
+
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER summaries_pool_notify_on_insert
+AFTER INSERT
+ON summaries
+FOR EACH ROW
+EXECUTE PROCEDURE pool_notify('pool_channel');
@@ -0,0 +1,3 @@
+-- Sent and failed messages are removed after their retention period
+CREATE INDEX IF NOT EXISTS outbox_sent_at_idx ON outbox (sent_at) WHERE status = 'sent';
+CREATE INDEX IF NOT EXISTS outbox_failed_created_at_idx ON outbox (created_at) WHERE status = 'failed';
@@ -0,0 +1,20 @@
+//! Personal address books with names of counterparties shown instead of their addresses.
+use crate::types::{AccountAddress, ContractAddress};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// Prefix of callback data of "Save sender" buttons, followed by the address.
+pub const SAVE_PREFIX: &str = "save:";
+
+/// Builds keyboard with a button for saving the sender's address to the address book.
+pub fn save_button(address: &str) -> InlineKeyboardMarkup {
+    let data = format!("{}{}", SAVE_PREFIX, address);
+    InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
+        "💾 Save sender".to_string(),
+        data,
+    )]])
+}
+
+/// Returns `true` if text is an account address or a contract address formatted as `<index,subindex>`.
+pub fn is_address(text: &str) -> bool {
+    text.parse::<AccountAddress>().is_ok() || text.parse::<ContractAddress>().is_ok()
+}
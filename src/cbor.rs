@@ -0,0 +1,350 @@
+//! Minimal CBOR decoder for short on-chain payloads such as transfer memos.
+//! More info: https://www.rfc-editor.org/rfc/rfc8949.html
+use std::fmt;
+
+const BREAK: u8 = 0xff;
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Unsigned(u64),
+    /// Negative integer `-1 - n`.
+    Negative(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Tag(u64, Box<Value>),
+    Bool(bool),
+    Null,
+    Undefined,
+    Float(f64),
+    Simple(u8),
+}
+
+/// Formats value in CBOR diagnostic notation.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Unsigned(n) => write!(f, "{}", n),
+            Value::Negative(n) => write!(f, "-{}", *n as u128 + 1),
+            Value::Bytes(bytes) => write!(f, "h'{}'", hex::encode(bytes)),
+            Value::Text(text) => write!(f, "{:?}", text),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Tag(tag, value) => write!(f, "{}({})", tag, value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Null => write!(f, "null"),
+            Value::Undefined => write!(f, "undefined"),
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Simple(value) => write!(f, "simple({})", value),
+        }
+    }
+}
+
+/// Decodes a single CBOR data item, which must span all the bytes.
+pub fn decode(bytes: &[u8]) -> Option<Value> {
+    let mut decoder = Decoder { bytes, pos: 0 };
+    let value = decoder.value(0)?;
+
+    if decoder.pos == bytes.len() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.remaining() {
+            return None;
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn uint(&mut self, len: usize) -> Option<u64> {
+        let bytes = self.take(len)?;
+        Some(bytes.iter().fold(0, |acc, b| acc << 8 | *b as u64))
+    }
+
+    /// Reads argument of a data item from the additional information bits.
+    fn argument(&mut self, info: u8) -> Option<u64> {
+        match info {
+            0..=23 => Some(info as u64),
+            24 => self.uint(1),
+            25 => self.uint(2),
+            26 => self.uint(4),
+            27 => self.uint(8),
+            _ => None,
+        }
+    }
+
+    /// Reads length of a definite-length item, which can't exceed the number of remaining bytes.
+    fn length(&mut self, info: u8) -> Option<usize> {
+        let len = self.argument(info)?;
+        if len > self.remaining() as u64 {
+            None
+        } else {
+            Some(len as usize)
+        }
+    }
+
+    fn string(&mut self, major: u8, info: u8) -> Option<Vec<u8>> {
+        if info != 31 {
+            let len = self.length(info)?;
+            return Some(self.take(len)?.to_vec());
+        }
+
+        // Indefinite-length string is a sequence of definite-length chunks of the same type
+        let mut bytes = Vec::new();
+        loop {
+            let initial = self.byte()?;
+            if initial == BREAK {
+                return Some(bytes);
+            }
+            if initial >> 5 != major || initial & 0x1f == 31 {
+                return None;
+            }
+            let len = self.length(initial & 0x1f)?;
+            bytes.extend_from_slice(self.take(len)?);
+        }
+    }
+
+    /// Reads items of an array until the break code, or the number of items given by `info`.
+    fn items(&mut self, info: u8, depth: usize) -> Option<Vec<Value>> {
+        let mut items = Vec::new();
+
+        if info == 31 {
+            while self.peek()? != BREAK {
+                items.push(self.value(depth + 1)?);
+            }
+            self.pos += 1;
+        } else {
+            for _ in 0..self.length(info)? {
+                items.push(self.value(depth + 1)?);
+            }
+        }
+
+        Some(items)
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        let initial = self.byte()?;
+        let (major, info) = (initial >> 5, initial & 0x1f);
+
+        let value = match major {
+            0 => Value::Unsigned(self.argument(info)?),
+            1 => Value::Negative(self.argument(info)?),
+            2 => Value::Bytes(self.string(major, info)?),
+            3 => Value::Text(String::from_utf8(self.string(major, info)?).ok()?),
+            4 => Value::Array(self.items(info, depth)?),
+            5 => {
+                let mut entries = Vec::new();
+                if info == 31 {
+                    while self.peek()? != BREAK {
+                        let key = self.value(depth + 1)?;
+                        entries.push((key, self.value(depth + 1)?));
+                    }
+                    self.pos += 1;
+                } else {
+                    for _ in 0..self.length(info)? {
+                        let key = self.value(depth + 1)?;
+                        entries.push((key, self.value(depth + 1)?));
+                    }
+                }
+                Value::Map(entries)
+            }
+            6 => {
+                let tag = self.argument(info)?;
+                Value::Tag(tag, Box::new(self.value(depth + 1)?))
+            }
+            _ => match info {
+                0..=19 => Value::Simple(info),
+                20 => Value::Bool(false),
+                21 => Value::Bool(true),
+                22 => Value::Null,
+                23 => Value::Undefined,
+                24 => Value::Simple(self.byte()?),
+                25 => Value::Float(f16_to_f64(self.uint(2)? as u16)),
+                26 => Value::Float(f32::from_bits(self.uint(4)? as u32) as f64),
+                27 => Value::Float(f64::from_bits(self.uint(8)?)),
+                _ => return None,
+            },
+        };
+
+        Some(value)
+    }
+}
+
+/// Converts half-precision float to `f64`.
+fn f16_to_f64(half: u16) -> f64 {
+    let exponent = (half >> 10) & 0x1f;
+    let mantissa = (half & 0x3ff) as f64;
+
+    let value = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mantissa + 1024.0) * 2f64.powi(exponent as i32 - 25),
+    };
+
+    if half & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_hex(data: &str) -> Option<Value> {
+        decode(&hex::decode(data).unwrap())
+    }
+
+    #[test]
+    fn decodes_data_items() {
+        let items = [
+            ("00", "0"),
+            ("17", "23"),
+            ("1818", "24"),
+            ("1b000000e8d4a51000", "1000000000000"),
+            ("20", "-1"),
+            ("3bffffffffffffffff", "-18446744073709551616"),
+            ("43010203", "h'010203'"),
+            ("6449455446", "\"IETF\""),
+            ("5f42010243030405ff", "h'0102030405'"),
+            ("7f657374726561646d696e67ff", "\"streaming\""),
+            ("8301820203820405", "[1, [2, 3], [4, 5]]"),
+            ("9f018202039f0405ffff", "[1, [2, 3], [4, 5]]"),
+            ("a26161016162820203", "{\"a\": 1, \"b\": [2, 3]}"),
+            ("bf6346756ef563416d7421ff", "{\"Fun\": true, \"Amt\": -2}"),
+            (
+                "c074323031332d30332d32315432303a30343a30305a",
+                "0(\"2013-03-21T20:04:00Z\")",
+            ),
+            ("f4", "false"),
+            ("f6", "null"),
+            ("f7", "undefined"),
+            ("f0", "simple(16)"),
+            ("f8ff", "simple(255)"),
+            ("f93c00", "1.0"),
+            ("f9c400", "-4.0"),
+            ("fa47c35000", "100000.0"),
+            ("fb3ff199999999999a", "1.1"),
+        ];
+        for (data, diagnostic) in &items {
+            match decode_hex(data) {
+                Some(value) => assert_eq!(value.to_string(), *diagnostic, "{}", data),
+                None => panic!("{} not decoded", data),
+            }
+        }
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth: usize| format!("{}80", "81".repeat(depth));
+        assert!(decode_hex(&nested(MAX_DEPTH)).is_some());
+        assert!(decode_hex(&nested(MAX_DEPTH + 1)).is_none());
+
+        let nested_indefinite = format!(
+            "{}{}",
+            "9f".repeat(MAX_DEPTH + 2),
+            "ff".repeat(MAX_DEPTH + 2)
+        );
+        assert!(decode_hex(&nested_indefinite).is_none());
+
+        let nested_tags = format!("{}00", "c1".repeat(MAX_DEPTH + 1));
+        assert!(decode_hex(&nested_tags).is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let items = [
+            "1b000000e8d4a51000",
+            "43010203",
+            "5f42010243030405ff",
+            "9f018202039f0405ffff",
+            "a26161016162820203",
+            "c074323031332d30332d32315432303a30343a30305a",
+            "fb3ff199999999999a",
+        ];
+        for data in &items {
+            for len in (0..data.len()).step_by(2) {
+                assert!(decode_hex(&data[..len]).is_none(), "{}", &data[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_lengths() {
+        let items = [
+            // Lengths exceeding the data
+            "5affffffff00",
+            "7b7fffffffffffffff61",
+            "9bffffffffffffffff00",
+            "bbffffffffffffffff0000",
+            // Reserved additional information
+            "1c",
+            "5d",
+            "fc",
+            // Indefinite-length string with chunks of another type or of indefinite length
+            "5f6161ff",
+            "5f5f4101ffff",
+            // Break code outside of an indefinite-length item
+            "ff",
+            "8201ff",
+            // Trailing bytes
+            "0000",
+            // Text which isn't UTF-8
+            "62c328",
+        ];
+        for data in &items {
+            assert!(decode_hex(data).is_none(), "{}", data);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! Decoder for event logs of CIS-2 token standard contracts.
+//! More info: https://proposals.concordium.software/CIS/cis-2.html#logged-events
+use crate::types::{AccountAddress, Address, ContractAddress};
+use base58check::ToBase58Check;
+use std::fmt;
+
+const TRANSFER: u8 = 255;
+const MINT: u8 = 254;
+const BURN: u8 = 253;
+const UPDATE_OPERATOR: u8 = 252;
+const TOKEN_METADATA: u8 = 251;
+
+#[derive(Debug)]
+pub struct TokenId(Vec<u8>);
+
+impl fmt::Display for TokenId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "(empty)")
+        } else {
+            write!(f, "{}", hex::encode(&self.0))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TokenEvent {
+    Transfer {
+        token_id: TokenId,
+        amount: u128,
+        from: Address,
+        to: Address,
+    },
+    Mint {
+        token_id: TokenId,
+        amount: u128,
+        owner: Address,
+    },
+    Burn {
+        token_id: TokenId,
+        amount: u128,
+        owner: Address,
+    },
+    UpdateOperator {
+        /// `true` if the operator was added, `false` if removed.
+        add: bool,
+        owner: Address,
+        operator: Address,
+    },
+    TokenMetadata {
+        token_id: TokenId,
+        url: String,
+    },
+}
+
+/// Decodes hex-encoded contract event log.
+/// Returns `None` if the log isn't a CIS-2 event.
+pub fn decode(log: &str) -> Option<TokenEvent> {
+    let bytes = hex::decode(log).ok()?;
+    let mut reader = Reader {
+        bytes: &bytes,
+        pos: 0,
+    };
+
+    let event = match reader.byte()? {
+        TRANSFER => TokenEvent::Transfer {
+            token_id: reader.token_id()?,
+            amount: reader.token_amount()?,
+            from: reader.address()?,
+            to: reader.address()?,
+        },
+        MINT => TokenEvent::Mint {
+            token_id: reader.token_id()?,
+            amount: reader.token_amount()?,
+            owner: reader.address()?,
+        },
+        BURN => TokenEvent::Burn {
+            token_id: reader.token_id()?,
+            amount: reader.token_amount()?,
+            owner: reader.address()?,
+        },
+        UPDATE_OPERATOR => TokenEvent::UpdateOperator {
+            add: match reader.byte()? {
+                0 => false,
+                1 => true,
+                _ => return None,
+            },
+            owner: reader.address()?,
+            operator: reader.address()?,
+        },
+        TOKEN_METADATA => {
+            let token_id = reader.token_id()?;
+            let len = reader.uint(2)? as usize;
+            let url = String::from_utf8(reader.take(len)?.to_vec()).ok()?;
+            // Optional SHA256 hash of the metadata
+            match reader.byte()? {
+                0 => {}
+                1 => {
+                    reader.take(32)?;
+                }
+                _ => return None,
+            }
+            TokenEvent::TokenMetadata { token_id, url }
+        }
+        _ => return None,
+    };
+
+    if reader.pos == bytes.len() {
+        Some(event)
+    } else {
+        None
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.bytes.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() - self.pos {
+            return None;
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Some(bytes)
+    }
+
+    /// Reads little-endian unsigned integer of `len` bytes.
+    fn uint(&mut self, len: usize) -> Option<u64> {
+        let bytes = self.take(len)?;
+        Some(bytes.iter().rev().fold(0, |acc, b| acc << 8 | *b as u64))
+    }
+
+    fn token_id(&mut self) -> Option<TokenId> {
+        let len = self.byte()? as usize;
+        Some(TokenId(self.take(len)?.to_vec()))
+    }
+
+    /// Reads token amount encoded as unsigned LEB128.
+    /// The standard allows amounts up to 2^256, but only those fitting into `u128` are supported.
+    fn token_amount(&mut self) -> Option<u128> {
+        let mut amount: u128 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            let value = (byte & 0x7f) as u128;
+            if shift >= 128 || (shift > 0 && value >> (128 - shift) != 0) {
+                return None;
+            }
+            amount |= value << shift;
+            if byte & 0x80 == 0 {
+                return Some(amount);
+            }
+            shift += 7;
+        }
+    }
+
+    fn address(&mut self) -> Option<Address> {
+        match self.byte()? {
+            0 => {
+                let address = self.take(32)?.to_base58check(1);
+                Some(Address::Account(AccountAddress::new(address)))
+            }
+            1 => {
+                let index = self.uint(8)?;
+                let subindex = self.uint(8)?;
+                Some(Address::Contract(ContractAddress { index, subindex }))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: &str = "001111111111111111111111111111111111111111111111111111111111111111";
+    const CONTRACT: &str = "0105000000000000000000000000000000";
+
+    fn account() -> String {
+        [0x11; 32].to_base58check(1)
+    }
+
+    fn contract() -> String {
+        ContractAddress {
+            index: 5,
+            subindex: 0,
+        }
+        .to_string()
+    }
+
+    #[test]
+    fn decodes_transfer() {
+        let log = format!("ff01aaac02{}{}", ACCOUNT, CONTRACT);
+        match decode(&log) {
+            Some(TokenEvent::Transfer {
+                token_id,
+                amount,
+                from,
+                to,
+            }) => {
+                assert_eq!(token_id.to_string(), "aa");
+                assert_eq!(amount, 300);
+                assert_eq!(from.to_string(), account());
+                assert_eq!(to.to_string(), contract());
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[test]
+    fn decodes_mint() {
+        let log = format!("fe0001{}", ACCOUNT);
+        match decode(&log) {
+            Some(TokenEvent::Mint {
+                token_id,
+                amount,
+                owner,
+            }) => {
+                assert_eq!(token_id.to_string(), "(empty)");
+                assert_eq!(amount, 1);
+                assert_eq!(owner.to_string(), account());
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[test]
+    fn decodes_burn() {
+        let log = format!("fd02aabb7f{}", ACCOUNT);
+        match decode(&log) {
+            Some(TokenEvent::Burn {
+                token_id,
+                amount,
+                owner,
+            }) => {
+                assert_eq!(token_id.to_string(), "aabb");
+                assert_eq!(amount, 127);
+                assert_eq!(owner.to_string(), account());
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[test]
+    fn decodes_update_operator() {
+        let log = format!("fc01{}{}", ACCOUNT, CONTRACT);
+        match decode(&log) {
+            Some(TokenEvent::UpdateOperator {
+                add,
+                owner,
+                operator,
+            }) => {
+                assert!(add);
+                assert_eq!(owner.to_string(), account());
+                assert_eq!(operator.to_string(), contract());
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+
+        let log = format!("fc00{}{}", ACCOUNT, CONTRACT);
+        assert!(matches!(
+            decode(&log),
+            Some(TokenEvent::UpdateOperator { add: false, .. })
+        ));
+    }
+
+    #[test]
+    fn decodes_token_metadata() {
+        // "https://a" without and with the metadata hash
+        let url = hex::encode("https://a");
+        for hash in &["00".to_owned(), format!("01{}", "22".repeat(32))] {
+            let log = format!("fb01aa0900{}{}", url, hash);
+            match decode(&log) {
+                Some(TokenEvent::TokenMetadata { token_id, url }) => {
+                    assert_eq!(token_id.to_string(), "aa");
+                    assert_eq!(url, "https://a");
+                }
+                event => panic!("unexpected {:?}", event),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_events() {
+        let logs = [
+            format!("ff01aaac02{}{}", ACCOUNT, CONTRACT),
+            format!("fe0001{}", ACCOUNT),
+            format!("fc01{}{}", ACCOUNT, CONTRACT),
+            format!(
+                "fb01aa0900{}01{}",
+                hex::encode("https://a"),
+                "22".repeat(32)
+            ),
+        ];
+        for log in &logs {
+            assert!(decode(log).is_some());
+            for len in (0..log.len()).step_by(2) {
+                assert!(decode(&log[..len]).is_none(), "{}", &log[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_events() {
+        let logs = [
+            // Not hex
+            "zz".to_owned(),
+            "f".to_owned(),
+            // Not a CIS-2 event
+            format!("fa01aaac02{}{}", ACCOUNT, CONTRACT),
+            // Trailing bytes
+            format!("fe0001{}00", ACCOUNT),
+            // Unknown address type
+            format!("fe0001{}", ACCOUNT.replacen("00", "02", 1)),
+            // Amount overflowing 128 bits
+            format!("fe00{}04{}", "ff".repeat(18), ACCOUNT),
+            // Token ID longer than the log
+            format!("fe05aa01{}", ACCOUNT),
+            // Operator flag other than 0 or 1
+            format!("fc02{}{}", ACCOUNT, CONTRACT),
+            // URL which isn't UTF-8
+            "fb01aa0100ff00".to_owned(),
+            // Invalid metadata hash flag
+            format!("fb01aa0900{}02", hex::encode("https://a")),
+        ];
+        for log in &logs {
+            assert!(decode(log).is_none(), "{}", log);
+        }
+    }
+}
@@ -15,16 +15,58 @@ pub enum Command {
     Subscriptions,
     #[command(description = "unsubscribe from on-chain events")]
     Unsubscribe,
+    #[command(description = "choose direction of transfers to be notified about")]
+    Direction,
+    #[command(description = "toggle notifications about chain parameter updates")]
+    ChainUpdates,
+    #[command(description = "set minimum amount of transfers to be notified about")]
+    Threshold,
+    #[command(description = "choose events to be notified about for an address")]
+    Settings,
+    #[command(description = "set label shown instead of an address")]
+    Rename,
+    #[command(description = "list names saved to the address book")]
+    AddressBook,
+    #[command(description = "receive notifications immediately or in hourly or daily digests")]
+    Digest,
+    #[command(description = "set hours when notifications are silent or deferred")]
+    Quiet,
 }
 
 type BotCmd = teloxide::types::BotCommand;
 
-pub fn commands() -> [BotCmd; 5] {
+pub fn commands() -> [BotCmd; 13] {
     [
         BotCmd::new("help", "show help"),
         BotCmd::new("balance", "get current balance for an address"),
         BotCmd::new("subscribe", "subscribe to on-chain events for an address"),
         BotCmd::new("subscriptions", "list subscribed addresses"),
         BotCmd::new("unsubscribe", "unsubscribe from on-chain events"),
+        BotCmd::new(
+            "direction",
+            "choose direction of transfers to be notified about",
+        ),
+        BotCmd::new(
+            "chainupdates",
+            "toggle notifications about chain parameter updates",
+        ),
+        BotCmd::new(
+            "threshold",
+            "set minimum amount of transfers to be notified about",
+        ),
+        BotCmd::new(
+            "settings",
+            "choose events to be notified about for an address",
+        ),
+        BotCmd::new("rename", "set label shown instead of an address"),
+        BotCmd::new("addressbook", "list names saved to the address book"),
+        BotCmd::new(
+            "digest",
+            "receive notifications immediately or in hourly or daily digests",
+        ),
+        BotCmd::new(
+            "quiet",
+            "set hours when notifications are silent or deferred",
+        ),
     ]
 }
@@ -1,78 +1,606 @@
-use crate::types::AccountAddress;
-use crate::{pg_pool, redis_cm};
+use crate::types::{
+    AccountAddress, Amount, Category, ContractAddress, DeliveryMode, DigestEvent, DigestSettings,
+    Direction, QuietHours, Subscriber, TransactionIndex,
+};
+use crate::{
+    pg_pool, redis_cm,
+    sender::{Claim, Delivery},
+};
 use base58check::ToBase58Check;
+use chrono::{FixedOffset, NaiveTime, Timelike};
 use redis::{aio::ConnectionManager, AsyncCommands, RedisResult};
-use sqlx::{postgres::PgRow, PgPool, Row};
+use sqlx::{postgres::PgRow, PgConnection, PgPool, Row};
+use std::collections::HashMap;
 use std::result::Result;
+use std::time::Duration;
+use teloxide::types::InlineKeyboardMarkup;
 use tokio_stream::StreamExt;
 
+/// Redis key of the set of Telegram user IDs subscribed to chain updates.
+const CHAIN_UPDATES_KEY: &str = "chain_updates";
+
+/// Redis key of the hash of delivery modes keyed by Telegram user ID.
+/// Users who receive notifications immediately are omitted.
+const DELIVERY_MODES_KEY: &str = "delivery_modes";
+
+/// Redis key of the hash of quiet hours keyed by Telegram user ID.
+const QUIET_HOURS_KEY: &str = "quiet_hours";
+
+/// Redis key of the hash of subscribed accounts keyed by account index,
+/// which is also ID of the baker pool the account may own.
+const POOL_OWNERS_KEY: &str = "pool_owners";
+
 /// Preloads all subscriptions from Postgres to Redis.
-/// For each subscriber account there are a set of Telegram user IDs.
+/// For each subscriber account there is a hash of subscriber settings keyed by Telegram user ID.
 /// Many users can subscribe to updates for one account.
 pub async fn load_subscriptions(pool: &PgPool) -> Result<(), sqlx::Error> {
     let mut cm = redis_cm().await.clone();
-    let account_keys: Vec<String> = cm.keys("account:*").await.unwrap();
-    for key in account_keys {
+    let mut keys: Vec<String> = cm.keys("account:*").await.unwrap();
+    let label_keys: Vec<String> = cm.keys("labels:*").await.unwrap();
+    let address_book_keys: Vec<String> = cm.keys("address_book:*").await.unwrap();
+    keys.extend(label_keys);
+    keys.extend(address_book_keys);
+    keys.push(POOL_OWNERS_KEY.to_owned());
+    for key in keys {
+        let _: () = cm.del(key).await.unwrap();
+    }
+
+    let mut rows = sqlx::query(
+        r#"
+SELECT account, user_id, direction, threshold, disabled_categories, label, account_index
+FROM subscriptions
+        "#,
+    )
+    .fetch(pool);
+
+    while let Some(row) = rows.try_next().await? {
+        let account = row.get::<&[u8], _>(0).to_base58check(1);
+        let subscriber = subscriber_from_row(&row);
+        cache_subscriber(&mut cm, &account, &subscriber).await;
+
+        if let Some(label) = row.get::<Option<&str>, _>(5) {
+            cache_label(&mut cm, subscriber.user_id, &account, label).await;
+        }
+        if let Some(account_index) = row.get::<Option<i64>, _>(6) {
+            let _: () = cm
+                .hset(POOL_OWNERS_KEY, account_index, &account)
+                .await
+                .unwrap();
+        }
+    }
+
+    let mut rows = sqlx::query("SELECT user_id, address, name FROM address_book").fetch(pool);
+    while let Some(row) = rows.try_next().await? {
+        let key = address_book_key(row.get(0));
+        let _: () = cm
+            .hset(key, row.get::<&str, _>(1), row.get::<&str, _>(2))
+            .await
+            .unwrap();
+    }
+
+    load_contract_subscriptions(pool, &mut cm).await?;
+    load_chain_update_subscriptions(pool, &mut cm).await?;
+    load_delivery_modes(pool, &mut cm).await?;
+    load_quiet_hours(pool, &mut cm).await
+}
+
+/// Preloads contract subscriptions from Postgres to Redis.
+/// For each contract there is a set of Telegram user IDs.
+async fn load_contract_subscriptions(
+    pool: &PgPool,
+    cm: &mut ConnectionManager,
+) -> Result<(), sqlx::Error> {
+    let contract_keys: Vec<String> = cm.keys("contract:*").await.unwrap();
+    for key in contract_keys {
         let _: () = cm.del(key).await.unwrap();
     }
 
+    let mut rows = sqlx::query(
+        "SELECT index, subindex, array_agg(user_id) FROM contract_subscriptions GROUP BY index, subindex",
+    )
+    .fetch(pool);
+
+    while let Some(row) = rows.try_next().await? {
+        let index: i64 = row.get(0);
+        let subindex: i64 = row.get(1);
+        let v: Vec<i64> = row.get(2);
+        let key = format!("contract:{},{}", index, subindex);
+        let _: () = cm.sadd(key, v).await.unwrap();
+    }
+
+    Ok(())
+}
+
+/// Preloads IDs of Telegram users subscribed to chain updates from Postgres to Redis.
+async fn load_chain_update_subscriptions(
+    pool: &PgPool,
+    cm: &mut ConnectionManager,
+) -> Result<(), sqlx::Error> {
+    let _: () = cm.del(CHAIN_UPDATES_KEY).await.unwrap();
+
+    let user_ids: Vec<i64> = sqlx::query("SELECT user_id FROM chain_update_subscriptions")
+        .map(|row: PgRow| row.get(0))
+        .fetch_all(pool)
+        .await?;
+
+    if !user_ids.is_empty() {
+        let _: () = cm.sadd(CHAIN_UPDATES_KEY, user_ids).await.unwrap();
+    }
+
+    Ok(())
+}
+
+/// Preloads delivery modes of Telegram users receiving digests from Postgres to Redis.
+async fn load_delivery_modes(pool: &PgPool, cm: &mut ConnectionManager) -> Result<(), sqlx::Error> {
+    let _: () = cm.del(DELIVERY_MODES_KEY).await.unwrap();
+
     let mut rows =
-        sqlx::query("SELECT account, array_agg(user_id) FROM subscriptions GROUP BY account")
+        sqlx::query("SELECT user_id, delivery_mode FROM users WHERE delivery_mode <> 'immediate'")
             .fetch(pool);
 
     while let Some(row) = rows.try_next().await? {
-        let k = row.get::<&[u8], _>(0).to_base58check(1);
-        let v: Vec<i64> = row.get(1);
-        let key = format!("account:{}", k);
-        let _: () = cm.sadd(key, v).await.unwrap();
+        let user_id: i64 = row.get(0);
+        let mode: DeliveryMode = row.get(1);
+        let _: () = cm
+            .hset(DELIVERY_MODES_KEY, user_id, mode.to_string())
+            .await
+            .unwrap();
+    }
+
+    Ok(())
+}
+
+/// Preloads quiet hours of Telegram users from Postgres to Redis.
+async fn load_quiet_hours(pool: &PgPool, cm: &mut ConnectionManager) -> Result<(), sqlx::Error> {
+    let _: () = cm.del(QUIET_HOURS_KEY).await.unwrap();
+
+    let mut rows = sqlx::query(
+        r#"
+SELECT user_id, quiet_start, quiet_end, utc_offset, quiet_mode FROM users
+WHERE quiet_start IS NOT NULL AND quiet_end IS NOT NULL
+        "#,
+    )
+    .fetch(pool);
+
+    while let Some(row) = rows.try_next().await? {
+        if let Some(quiet_hours) = quiet_hours_from_row(&row) {
+            cache_quiet_hours(cm, row.get(0), &quiet_hours).await;
+        }
     }
 
     Ok(())
 }
 
-pub async fn subscriber_ids(
+/// Reads quiet hours from `user_id`, `quiet_start`, `quiet_end`, `utc_offset` and `quiet_mode`
+/// columns, `None` if they're disabled.
+fn quiet_hours_from_row(row: &PgRow) -> Option<QuietHours> {
+    let start: Option<i32> = row.get(1);
+    let end: Option<i32> = row.get(2);
+    match (start, end) {
+        (Some(start), Some(end)) => Some(QuietHours {
+            start: start as u32,
+            end: end as u32,
+            utc_offset: row.get(3),
+            mode: row.get(4),
+        }),
+        _ => None,
+    }
+}
+
+async fn cache_quiet_hours(cm: &mut ConnectionManager, user_id: i64, quiet_hours: &QuietHours) {
+    let value = serde_json::to_string(quiet_hours).unwrap();
+    let _: () = cm.hset(QUIET_HOURS_KEY, user_id, value).await.unwrap();
+}
+
+/// Reads subscriber settings from `user_id`, `direction`, `threshold`
+/// and `disabled_categories` columns.
+fn subscriber_from_row(row: &PgRow) -> Subscriber {
+    let threshold: Option<i64> = row.get("threshold");
+    let disabled_categories: Vec<String> = row.get("disabled_categories");
+    Subscriber {
+        user_id: row.get("user_id"),
+        direction: row.get("direction"),
+        threshold: threshold.map(|threshold| Amount::from(threshold as u64)),
+        disabled_categories: disabled_categories
+            .iter()
+            .filter_map(|category| category.parse().ok())
+            .collect(),
+    }
+}
+
+fn contract_key(contract: &ContractAddress) -> String {
+    format!("contract:{},{}", contract.index, contract.subindex)
+}
+
+async fn cache_subscriber(cm: &mut ConnectionManager, account: &str, subscriber: &Subscriber) {
+    let key = format!("account:{}", account);
+    let value = serde_json::to_string(subscriber).unwrap();
+    let _: () = cm.hset(key, subscriber.user_id, value).await.unwrap();
+}
+
+/// For each Telegram user there is a hash of account labels keyed by account address.
+fn labels_key(user_id: i64) -> String {
+    format!("labels:{}", user_id)
+}
+
+async fn cache_label(cm: &mut ConnectionManager, user_id: i64, account: &str, label: &str) {
+    let _: () = cm.hset(labels_key(user_id), account, label).await.unwrap();
+}
+
+/// For each Telegram user there is a hash of address book names keyed by address.
+fn address_book_key(user_id: i64) -> String {
+    format!("address_book:{}", user_id)
+}
+
+/// Returns labels of subscribed accounts and names from the address book of a Telegram user
+/// for `addresses`, keyed by address. Labels of subscribed accounts take precedence.
+pub async fn labels(
+    cm: &mut ConnectionManager,
+    user_id: i64,
+    addresses: &[&str],
+) -> RedisResult<HashMap<String, String>> {
+    let mut labels = HashMap::new();
+    if addresses.is_empty() {
+        return Ok(labels);
+    }
+
+    for key in &[address_book_key(user_id), labels_key(user_id)] {
+        // `hget` would send HGET for a single address, which doesn't reply with a list
+        let values: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(key)
+            .arg(addresses)
+            .query_async(cm)
+            .await?;
+        for (address, label) in addresses.iter().zip(values) {
+            if let Some(label) = label {
+                labels.insert(address.to_string(), label);
+            }
+        }
+    }
+    Ok(labels)
+}
+
+/// Saves name for an account or contract address to the user's address book.
+/// `None` removes the address from the address book. Returns `false` if there was nothing to remove.
+pub async fn save_address(
+    user_id: i64,
+    address: &str,
+    name: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    let affected = if let Some(name) = name {
+        sqlx::query(
+            r#"
+INSERT INTO address_book (user_id, address, name) VALUES ($1, $2, $3)
+ON CONFLICT (user_id, address) DO UPDATE SET name = EXCLUDED.name
+            "#,
+        )
+        .bind(user_id)
+        .bind(address)
+        .bind(name)
+        .execute(pool)
+        .await?;
+
+        let _: () = cm
+            .hset(address_book_key(user_id), address, name)
+            .await
+            .unwrap();
+        1
+    } else {
+        let affected = sqlx::query("DELETE FROM address_book WHERE user_id = $1 AND address = $2")
+            .bind(user_id)
+            .bind(address)
+            .execute(pool)
+            .await?
+            .rows_affected();
+
+        let _: () = cm.hdel(address_book_key(user_id), address).await.unwrap();
+        affected
+    };
+
+    Ok(affected > 0)
+}
+
+/// Returns address book of a Telegram user formatted as `name: address`.
+pub async fn address_book(user_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    sqlx::query("SELECT name, address FROM address_book WHERE user_id = $1 ORDER BY name")
+        .bind(user_id)
+        .map(|row: PgRow| format!("{}: {}", row.get::<&str, _>(0), row.get::<&str, _>(1)))
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns settings of all subscribers for an account.
+pub async fn subscribers(
     cm: &mut ConnectionManager,
     account: &str,
-) -> RedisResult<Option<Vec<i64>>> {
+) -> RedisResult<Vec<Subscriber>> {
     let key = format!("account:{}", account);
-    let user_ids: Option<Vec<i64>> = cm.smembers(key).await?;
+    let values: Vec<String> = cm.hvals(key).await?;
+    let subscribers = values
+        .iter()
+        .map(|value| serde_json::from_str(value).unwrap())
+        .collect();
+    Ok(subscribers)
+}
+
+/// Returns the subscribed account owning the baker pool.
+pub async fn pool_owner(
+    cm: &mut ConnectionManager,
+    baker_id: u64,
+) -> RedisResult<Option<AccountAddress>> {
+    let account: Option<String> = cm.hget(POOL_OWNERS_KEY, baker_id).await?;
+    Ok(account.map(AccountAddress::new))
+}
+
+/// Returns IDs of Telegram users subscribed to a contract.
+pub async fn contract_subscriber_ids(
+    cm: &mut ConnectionManager,
+    contract: &ContractAddress,
+) -> RedisResult<Vec<i64>> {
+    let user_ids: Vec<i64> = cm.smembers(contract_key(contract)).await?;
+    Ok(user_ids)
+}
+
+/// Returns IDs of Telegram users subscribed to chain updates.
+pub async fn chain_update_subscriber_ids(cm: &mut ConnectionManager) -> RedisResult<Vec<i64>> {
+    let user_ids: Vec<i64> = cm.smembers(CHAIN_UPDATES_KEY).await?;
     Ok(user_ids)
 }
 
 /// Returns subscriptions for a Telegram user.
+/// Labelled accounts are formatted as `label: address`,
+/// contract addresses are formatted as `<index,subindex>`.
 pub async fn subscriptions(user_id: i64) -> Result<Vec<String>, sqlx::Error> {
     let pool = pg_pool().await;
 
-    let subscriptions = sqlx::query("SELECT account FROM subscriptions WHERE user_id = $1")
-        .bind(user_id)
-        .map(|row: PgRow| row.get::<&[u8], _>(0).to_base58check(1))
-        .fetch_all(pool)
-        .await?;
+    let mut subscriptions =
+        sqlx::query("SELECT account, label FROM subscriptions WHERE user_id = $1 ORDER BY id")
+            .bind(user_id)
+            .map(|row: PgRow| {
+                let account = row.get::<&[u8], _>(0).to_base58check(1);
+                match row.get::<Option<&str>, _>(1) {
+                    Some(label) => format!("{}: {}", label, account),
+                    None => account,
+                }
+            })
+            .fetch_all(pool)
+            .await?;
 
+    let contracts = sqlx::query(
+        "SELECT index, subindex FROM contract_subscriptions WHERE user_id = $1 ORDER BY id",
+    )
+    .bind(user_id)
+    .map(|row: PgRow| {
+        let index: i64 = row.get(0);
+        let subindex: i64 = row.get(1);
+        format!("<{},{}>", index, subindex)
+    })
+    .fetch_all(pool)
+    .await?;
+
+    subscriptions.extend(contracts);
     Ok(subscriptions)
 }
 
-pub async fn subscribe(user_id: i64, address: &AccountAddress) -> Result<bool, sqlx::Error> {
+/// Subscribes the user to an account, optionally with a minimum amount of transfers and a label.
+pub async fn subscribe(
+    user_id: i64,
+    address: &AccountAddress,
+    threshold: Option<Amount>,
+    label: Option<&str>,
+) -> Result<bool, sqlx::Error> {
     let mut cm = redis_cm().await.clone();
     let pool = pg_pool().await;
-    let ids: Vec<i64> = sqlx::query(
+    let subscriber = sqlx::query(
         r#"
-INSERT INTO subscriptions (user_id, account) VALUES ($1, $2)
-ON CONFLICT DO NOTHING RETURNING user_id
+INSERT INTO subscriptions (user_id, account, threshold, label) VALUES ($1, $2, $3, $4)
+ON CONFLICT DO NOTHING RETURNING user_id, direction, threshold, disabled_categories
         "#,
     )
     .bind(user_id)
     .bind(address.to_bytes())
-    .map(|row: PgRow| row.get(0))
-    .fetch_all(pool)
+    .bind(threshold.map(|threshold| threshold.micro_ccd() as i64))
+    .bind(label)
+    .map(|row: PgRow| subscriber_from_row(&row))
+    .fetch_optional(pool)
     .await?;
 
-    if ids.len() > 0 {
-        let key = format!("account:{}", address);
-        let _: () = cm.sadd(key, &ids).await.unwrap();
+    if let Some(subscriber) = &subscriber {
+        cache_subscriber(&mut cm, address.address(), subscriber).await;
+        if let Some(label) = label {
+            cache_label(&mut cm, user_id, address.address(), label).await;
+        }
     }
 
-    Ok(ids.len() > 0)
+    Ok(subscriber.is_some())
+}
+
+/// Returns subscribed accounts whose index isn't known yet.
+pub async fn unindexed_accounts() -> Result<Vec<AccountAddress>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    let accounts =
+        sqlx::query("SELECT DISTINCT account FROM subscriptions WHERE account_index IS NULL")
+            .map(|row: PgRow| AccountAddress::new(row.get::<&[u8], _>(0).to_base58check(1)))
+            .fetch_all(pool)
+            .await?;
+
+    Ok(accounts)
+}
+
+/// Stores index of a subscribed account, so that the account is notified about its baker pool.
+pub async fn set_account_index(
+    address: &AccountAddress,
+    account_index: u64,
+) -> Result<(), sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    sqlx::query("UPDATE subscriptions SET account_index = $2 WHERE account = $1")
+        .bind(address.to_bytes())
+        .bind(account_index as i64)
+        .execute(pool)
+        .await?;
+
+    let _: () = cm
+        .hset(POOL_OWNERS_KEY, account_index, address.address())
+        .await
+        .unwrap();
+    Ok(())
+}
+
+/// Sets label of a subscribed account shown instead of its address. `None` removes the label.
+pub async fn set_label(
+    user_id: i64,
+    address: &AccountAddress,
+    label: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    let updated =
+        sqlx::query("UPDATE subscriptions SET label = $3 WHERE user_id = $1 AND account = $2")
+            .bind(user_id)
+            .bind(address.to_bytes())
+            .bind(label)
+            .execute(pool)
+            .await?
+            .rows_affected();
+
+    if updated > 0 {
+        match label {
+            Some(label) => cache_label(&mut cm, user_id, address.address(), label).await,
+            None => {
+                let _: () = cm
+                    .hdel(labels_key(user_id), address.address())
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    Ok(updated > 0)
+}
+
+/// Sets direction of transfers the user is notified about for a subscribed account.
+pub async fn set_direction(
+    user_id: i64,
+    address: &AccountAddress,
+    direction: Direction,
+) -> Result<bool, sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    let subscriber = sqlx::query(
+        r#"
+UPDATE subscriptions SET direction = $3 WHERE user_id = $1 AND account = $2
+RETURNING user_id, direction, threshold, disabled_categories
+        "#,
+    )
+    .bind(user_id)
+    .bind(address.to_bytes())
+    .bind(direction)
+    .map(|row: PgRow| subscriber_from_row(&row))
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(subscriber) = &subscriber {
+        cache_subscriber(&mut cm, address.address(), subscriber).await;
+    }
+
+    Ok(subscriber.is_some())
+}
+
+/// Sets minimum amount of transfers the user is notified about for a subscribed account.
+/// `None` removes the threshold.
+pub async fn set_threshold(
+    user_id: i64,
+    address: &AccountAddress,
+    threshold: Option<Amount>,
+) -> Result<bool, sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    let subscriber = sqlx::query(
+        r#"
+UPDATE subscriptions SET threshold = $3 WHERE user_id = $1 AND account = $2
+RETURNING user_id, direction, threshold, disabled_categories
+        "#,
+    )
+    .bind(user_id)
+    .bind(address.to_bytes())
+    .bind(threshold.map(|threshold| threshold.micro_ccd() as i64))
+    .map(|row: PgRow| subscriber_from_row(&row))
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(subscriber) = &subscriber {
+        cache_subscriber(&mut cm, address.address(), subscriber).await;
+    }
+
+    Ok(subscriber.is_some())
+}
+
+/// Returns ID and settings of the user's subscription to an account.
+pub async fn subscription_settings(
+    user_id: i64,
+    address: &AccountAddress,
+) -> Result<Option<(i32, Subscriber)>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    sqlx::query(
+        r#"
+SELECT id, user_id, direction, threshold, disabled_categories FROM subscriptions
+WHERE user_id = $1 AND account = $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(address.to_bytes())
+    .map(|row: PgRow| (row.get(0), subscriber_from_row(&row)))
+    .fetch_optional(pool)
+    .await
+}
+
+/// Enables category of events for the user's subscription with ID if it's disabled,
+/// disables it otherwise. Returns subscribed account and updated settings.
+pub async fn toggle_category(
+    user_id: i64,
+    id: i32,
+    category: Category,
+) -> Result<Option<(AccountAddress, Subscriber)>, sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    let subscription = sqlx::query(
+        r#"
+UPDATE subscriptions SET disabled_categories = CASE
+    WHEN $3 = ANY(disabled_categories) THEN array_remove(disabled_categories, $3)
+    ELSE array_append(disabled_categories, $3)
+END
+WHERE id = $1 AND user_id = $2
+RETURNING account, user_id, direction, threshold, disabled_categories
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(category.key())
+    .map(|row: PgRow| {
+        let account = row.get::<&[u8], _>(0).to_base58check(1);
+        (AccountAddress::new(account), subscriber_from_row(&row))
+    })
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some((account, subscriber)) = &subscription {
+        cache_subscriber(&mut cm, account.address(), subscriber).await;
+    }
+
+    Ok(subscription)
 }
 
 pub async fn unsubscribe(user_id: i64, address: &AccountAddress) -> Result<bool, sqlx::Error> {
@@ -90,7 +618,11 @@ pub async fn unsubscribe(user_id: i64, address: &AccountAddress) -> Result<bool,
 
     if ids.len() > 0 {
         let key = format!("account:{}", address);
-        let _: () = cm.srem(key, &ids).await.unwrap();
+        let _: () = cm.hdel(key, &ids).await.unwrap();
+        let _: () = cm
+            .hdel(labels_key(user_id), address.address())
+            .await
+            .unwrap();
     }
 
     Ok(ids.len() > 0)
@@ -109,36 +641,893 @@ pub async fn unsubscribe_all(user_id: i64) -> Result<bool, sqlx::Error> {
 
     for (user_id, account) in &pairs {
         let key = format!("account:{}", account);
+        let _: () = cm.hdel(key, user_id).await.unwrap();
+    }
+    let _: () = cm.del(labels_key(user_id)).await.unwrap();
+
+    let contracts: Vec<(i64, i64)> = sqlx::query(
+        "DELETE FROM contract_subscriptions WHERE user_id = $1 RETURNING index, subindex",
+    )
+    .bind(user_id)
+    .map(|row: PgRow| (row.get(0), row.get(1)))
+    .fetch_all(pool)
+    .await?;
+
+    for (index, subindex) in &contracts {
+        let key = format!("contract:{},{}", index, subindex);
         let _: () = cm.srem(key, user_id).await.unwrap();
     }
 
-    Ok(pairs.len() > 0)
+    let chain_updates = sqlx::query("DELETE FROM chain_update_subscriptions WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+    if chain_updates > 0 {
+        let _: () = cm.srem(CHAIN_UPDATES_KEY, user_id).await.unwrap();
+    }
+
+    Ok(!pairs.is_empty() || !contracts.is_empty() || chain_updates > 0)
 }
 
-/// Returns account updates since account transaction index ID.
-pub async fn account_updates_since(
-    index_id: i64,
-) -> Result<Vec<(i64, String, String)>, sqlx::Error> {
+/// Removes subscriptions and pending messages of a Telegram user whose chat became unavailable,
+/// e.g. the user blocked the bot.
+pub async fn deactivate_user(user_id: i64) -> Result<(), sqlx::Error> {
     let pool = pg_pool().await;
+    unsubscribe_all(user_id).await?;
 
-    let updates = sqlx::query(
+    sqlx::query("DELETE FROM digest_events WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    sqlx::query("DELETE FROM deferred_messages WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
         r#"
-SELECT ati.id, ati.account, sm.summary::text FROM ati
-JOIN summaries AS sm ON ati.summary = sm.id
-WHERE ati.id > $1 AND ati.account IN (SELECT DISTINCT ON (account) account FROM subscriptions)
-ORDER BY ati.id
+UPDATE outbox SET status = 'failed', last_error = 'chat is unavailable'
+WHERE user_id = $1 AND status = 'pending'
         "#,
     )
-    .bind(index_id)
-    .map(|row: PgRow| {
-        (
-            row.get(0),
-            row.get::<&[u8], _>(1).to_base58check(1),
-            row.get(2),
-        )
-    })
-    .fetch_all(pool)
+    .bind(user_id)
+    .execute(pool)
     .await?;
 
-    Ok(updates)
+    Ok(())
+}
+
+/// Subscribes the user to chain updates, or unsubscribes if already subscribed.
+/// Returns `true` if the user is subscribed afterwards.
+pub async fn toggle_chain_updates(user_id: i64) -> Result<bool, sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    let removed = sqlx::query("DELETE FROM chain_update_subscriptions WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+    if removed > 0 {
+        let _: () = cm.srem(CHAIN_UPDATES_KEY, user_id).await.unwrap();
+        return Ok(false);
+    }
+
+    sqlx::query(
+        "INSERT INTO chain_update_subscriptions (user_id) VALUES ($1) ON CONFLICT DO NOTHING",
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    let _: () = cm.sadd(CHAIN_UPDATES_KEY, user_id).await.unwrap();
+    Ok(true)
+}
+
+pub async fn subscribe_contract(
+    user_id: i64,
+    contract: &ContractAddress,
+) -> Result<bool, sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    let ids: Vec<i64> = sqlx::query(
+        r#"
+INSERT INTO contract_subscriptions (user_id, index, subindex) VALUES ($1, $2, $3)
+ON CONFLICT DO NOTHING RETURNING user_id
+        "#,
+    )
+    .bind(user_id)
+    .bind(contract.index as i64)
+    .bind(contract.subindex as i64)
+    .map(|row: PgRow| row.get(0))
+    .fetch_all(pool)
+    .await?;
+
+    if !ids.is_empty() {
+        let _: () = cm.sadd(contract_key(contract), &ids).await.unwrap();
+    }
+
+    Ok(!ids.is_empty())
+}
+
+pub async fn unsubscribe_contract(
+    user_id: i64,
+    contract: &ContractAddress,
+) -> Result<bool, sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    let ids: Vec<i64> = sqlx::query(
+        r#"
+DELETE FROM contract_subscriptions WHERE user_id = $1 AND index = $2 AND subindex = $3
+RETURNING user_id
+        "#,
+    )
+    .bind(user_id)
+    .bind(contract.index as i64)
+    .bind(contract.subindex as i64)
+    .map(|row: PgRow| row.get(0))
+    .fetch_all(pool)
+    .await?;
+
+    if !ids.is_empty() {
+        let _: () = cm.srem(contract_key(contract), &ids).await.unwrap();
+    }
+
+    Ok(!ids.is_empty())
+}
+
+/// Returns delivery mode of a Telegram user.
+pub async fn delivery_mode(cm: &mut ConnectionManager, user_id: i64) -> RedisResult<DeliveryMode> {
+    let mode: Option<String> = cm.hget(DELIVERY_MODES_KEY, user_id).await?;
+    let mode = mode
+        .and_then(|mode| mode.parse().ok())
+        .unwrap_or(DeliveryMode::Immediate);
+    Ok(mode)
+}
+
+/// Returns digest delivery settings of a Telegram user.
+pub async fn digest_settings(user_id: i64) -> Result<Option<DigestSettings>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    sqlx::query(
+        r#"
+SELECT user_id, delivery_mode, utc_offset, digest_time, extract(epoch FROM last_digest_at)::bigint
+FROM users WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .map(|row: PgRow| digest_settings_from_row(&row))
+    .fetch_optional(pool)
+    .await
+}
+
+fn digest_settings_from_row(row: &PgRow) -> DigestSettings {
+    DigestSettings::new(row.get(0), row.get(1), row.get(2), row.get(3), row.get(4))
+}
+
+/// Sets delivery mode of a Telegram user. The next digest includes events since now.
+pub async fn set_delivery_mode(user_id: i64, mode: DeliveryMode) -> Result<(), sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    sqlx::query(
+        r#"
+INSERT INTO users (user_id, delivery_mode, last_digest_at) VALUES ($1, $2, now())
+ON CONFLICT (user_id) DO UPDATE SET delivery_mode = EXCLUDED.delivery_mode, last_digest_at = now()
+        "#,
+    )
+    .bind(user_id)
+    .bind(mode)
+    .execute(pool)
+    .await?;
+
+    if mode == DeliveryMode::Immediate {
+        let _: () = cm.hdel(DELIVERY_MODES_KEY, user_id).await.unwrap();
+    } else {
+        let _: () = cm
+            .hset(DELIVERY_MODES_KEY, user_id, mode.to_string())
+            .await
+            .unwrap();
+    }
+
+    Ok(())
+}
+
+/// Sets local time of daily digests and offset from UTC of the user's time zone,
+/// which is shared with quiet hours.
+pub async fn set_digest_time(
+    user_id: i64,
+    digest_time: NaiveTime,
+    utc_offset: FixedOffset,
+) -> Result<(), sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    let row = sqlx::query(
+        r#"
+INSERT INTO users (user_id, utc_offset, digest_time) VALUES ($1, $2, $3)
+ON CONFLICT (user_id) DO UPDATE SET utc_offset = EXCLUDED.utc_offset, digest_time = EXCLUDED.digest_time
+RETURNING user_id, quiet_start, quiet_end, utc_offset, quiet_mode
+        "#,
+    )
+    .bind(user_id)
+    .bind(utc_offset.local_minus_utc())
+    .bind(digest_time.num_seconds_from_midnight() as i32)
+    .fetch_one(pool)
+    .await?;
+
+    if let Some(quiet_hours) = quiet_hours_from_row(&row) {
+        cache_quiet_hours(&mut cm, user_id, &quiet_hours).await;
+    }
+
+    Ok(())
+}
+
+/// Stores event to be included in the next digest of a Telegram user.
+pub async fn add_digest_event(
+    conn: &mut PgConnection,
+    user_id: i64,
+    event: &DigestEvent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+INSERT INTO digest_events (user_id, account, category, direction, amount) VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(user_id)
+    .bind(event.account.to_bytes())
+    .bind(event.category.key())
+    .bind(event.direction)
+    .bind(event.amount.map(|amount| amount.micro_ccd() as i64))
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns digest delivery settings of Telegram users having pending digest events.
+pub async fn pending_digests() -> Result<Vec<DigestSettings>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    sqlx::query(
+        r#"
+SELECT user_id, delivery_mode, utc_offset, digest_time, extract(epoch FROM last_digest_at)::bigint
+FROM users WHERE user_id IN (SELECT DISTINCT user_id FROM digest_events)
+        "#,
+    )
+    .map(|row: PgRow| digest_settings_from_row(&row))
+    .fetch_all(pool)
+    .await
+}
+
+/// Account, category and direction of transfers with number of events and their total amount.
+pub type DigestEntry = (
+    AccountAddress,
+    Category,
+    Option<Direction>,
+    i64,
+    Option<Amount>,
+);
+
+/// Returns number of pending digest events and total amount for each account, category
+/// and direction of transfers of a Telegram user, with the claim removing the events
+/// once the digest is stored.
+pub async fn digest(user_id: i64) -> Result<(Vec<DigestEntry>, Claim), sqlx::Error> {
+    let pool = pg_pool().await;
+
+    let rows = sqlx::query(
+        r#"
+SELECT account, category, direction, count(*), sum(amount)::bigint, max(id) FROM digest_events
+WHERE user_id = $1 GROUP BY account, category, direction ORDER BY account, category, direction
+        "#,
+    )
+    .bind(user_id)
+    .map(|row: PgRow| {
+        let account = row.get::<&[u8], _>(0).to_base58check(1);
+        let category = row.get::<&str, _>(1).parse().unwrap_or(Category::Other);
+        let amount: Option<i64> = row.get(4);
+        let entry = (
+            AccountAddress::new(account),
+            category,
+            row.get(2),
+            row.get(3),
+            amount.map(|amount| Amount::from(amount as u64)),
+        );
+        (entry, row.get::<i64, _>(5))
+    })
+    .fetch_all(pool)
+    .await?;
+
+    let claim = Claim::Digest {
+        user_id,
+        up_to: rows.iter().map(|(_, id)| *id).max().unwrap_or(0),
+        count: rows.iter().map(|((_, _, _, count, _), _)| *count).sum(),
+    };
+    let digest = rows.into_iter().map(|(entry, _)| entry).collect();
+    Ok((digest, claim))
+}
+
+/// Returns quiet hours of a Telegram user, `None` if they're disabled.
+pub async fn quiet_hours(
+    cm: &mut ConnectionManager,
+    user_id: i64,
+) -> RedisResult<Option<QuietHours>> {
+    let value: Option<String> = cm.hget(QUIET_HOURS_KEY, user_id).await?;
+    Ok(value.map(|value| serde_json::from_str(&value).unwrap()))
+}
+
+/// Sets quiet hours of a Telegram user along with offset from UTC of the user's time zone.
+/// `None` disables quiet hours.
+pub async fn set_quiet_hours(
+    user_id: i64,
+    quiet_hours: Option<&QuietHours>,
+) -> Result<(), sqlx::Error> {
+    let mut cm = redis_cm().await.clone();
+    let pool = pg_pool().await;
+
+    match quiet_hours {
+        Some(quiet_hours) => {
+            sqlx::query(
+                r#"
+INSERT INTO users (user_id, quiet_start, quiet_end, utc_offset, quiet_mode) VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (user_id) DO UPDATE SET quiet_start = EXCLUDED.quiet_start, quiet_end = EXCLUDED.quiet_end,
+    utc_offset = EXCLUDED.utc_offset, quiet_mode = EXCLUDED.quiet_mode
+                "#,
+            )
+            .bind(user_id)
+            .bind(quiet_hours.start as i32)
+            .bind(quiet_hours.end as i32)
+            .bind(quiet_hours.utc_offset)
+            .bind(quiet_hours.mode)
+            .execute(pool)
+            .await?;
+
+            cache_quiet_hours(&mut cm, user_id, quiet_hours).await;
+        }
+        None => {
+            sqlx::query("UPDATE users SET quiet_start = NULL, quiet_end = NULL WHERE user_id = $1")
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+
+            let _: () = cm.hdel(QUIET_HOURS_KEY, user_id).await.unwrap();
+        }
+    }
+
+    Ok(())
+}
+
+/// Stores message to be sent to a Telegram user when quiet hours end.
+pub async fn defer_message(
+    conn: &mut PgConnection,
+    user_id: i64,
+    text: &str,
+    reply_markup: Option<&InlineKeyboardMarkup>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO deferred_messages (user_id, text, reply_markup) VALUES ($1, $2, $3)")
+        .bind(user_id)
+        .bind(text)
+        .bind(reply_markup.map(|markup| serde_json::to_string(markup).unwrap()))
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns IDs of Telegram users having deferred messages.
+pub async fn deferred_user_ids() -> Result<Vec<i64>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    sqlx::query("SELECT DISTINCT user_id FROM deferred_messages")
+        .map(|row: PgRow| row.get(0))
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns deferred messages of a Telegram user with their IDs in the order they were deferred.
+/// A message is removed once it's stored in the outbox, see `claim`.
+pub async fn deferred_messages(
+    user_id: i64,
+) -> Result<Vec<(i64, String, Option<InlineKeyboardMarkup>)>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    sqlx::query(
+        "SELECT id, text, reply_markup FROM deferred_messages WHERE user_id = $1 ORDER BY id",
+    )
+    .bind(user_id)
+    .map(|row: PgRow| {
+        let reply_markup = row
+            .get::<Option<&str>, _>(2)
+            .and_then(|markup| serde_json::from_str(markup).ok());
+        (row.get(0), row.get(1), reply_markup)
+    })
+    .fetch_all(pool)
+    .await
+}
+
+/// Stores message to a Telegram user in the outbox. Returns ID of the outbox entry.
+pub async fn add_to_outbox(
+    conn: &mut PgConnection,
+    user_id: i64,
+    text: &str,
+    reply_markup: Option<&InlineKeyboardMarkup>,
+    critical: bool,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO outbox (user_id, text, reply_markup, critical) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(user_id)
+    .bind(text)
+    .bind(reply_markup.map(|markup| serde_json::to_string(markup).unwrap()))
+    .bind(critical)
+    .map(|row: PgRow| row.get(0))
+    .fetch_one(conn)
+    .await
+}
+
+/// Removes messages sent earlier than `sent_retention` ago and messages failed earlier
+/// than `failed_retention` ago from the outbox. Returns the number of removed messages.
+pub async fn remove_old_deliveries(
+    sent_retention: Duration,
+    failed_retention: Duration,
+) -> Result<u64, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    let removed = sqlx::query(
+        r#"
+DELETE FROM outbox
+WHERE (status = 'sent' AND sent_at < now() - make_interval(secs => $1))
+    OR (status = 'failed' AND created_at < now() - make_interval(secs => $2))
+        "#,
+    )
+    .bind(sent_retention.as_secs_f64())
+    .bind(failed_retention.as_secs_f64())
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(removed)
+}
+
+/// Returns the latest handled transaction index ID of the source of updates,
+/// falling back to the progress stored in Redis by previous versions.
+pub async fn latest_index_id(source: &str) -> Result<Option<i64>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    let latest_id = sqlx::query("SELECT latest_id FROM progress WHERE source = $1")
+        .bind(source)
+        .map(|row: PgRow| row.get(0))
+        .fetch_optional(pool)
+        .await?;
+
+    match latest_id {
+        Some(latest_id) => Ok(Some(latest_id)),
+        None => {
+            let mut cm = redis_cm().await.clone();
+            let latest_id: Option<i64> = cm.get(format!("{}:latest", source)).await.unwrap();
+            Ok(latest_id)
+        }
+    }
+}
+
+/// Advances the progress of the source of updates to the transaction index
+/// and forgets recipients of messages about earlier transactions.
+pub async fn set_latest_index(
+    conn: &mut PgConnection,
+    index: TransactionIndex,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+INSERT INTO progress (source, latest_id) VALUES ($1, $2)
+ON CONFLICT (source) DO UPDATE SET latest_id = greatest(progress.latest_id, EXCLUDED.latest_id)
+        "#,
+    )
+    .bind(index.source())
+    .bind(index.id())
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("DELETE FROM handled_messages WHERE source = $1 AND index_id < $2")
+        .bind(index.source())
+        .bind(index.id())
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Records that message number `seq` about the transaction was handled for a Telegram user.
+/// Returns `false` if it was handled already, i.e. the transaction is replayed.
+pub async fn mark_handled(
+    conn: &mut PgConnection,
+    index: TransactionIndex,
+    seq: i32,
+    user_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query(
+        r#"
+INSERT INTO handled_messages (source, index_id, seq, user_id) VALUES ($1, $2, $3, $4)
+ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(index.source())
+    .bind(index.id())
+    .bind(seq)
+    .bind(user_id)
+    .execute(conn)
+    .await?
+    .rows_affected();
+
+    Ok(inserted > 0)
+}
+
+/// Returns pending messages from the outbox with delays until their next attempts.
+pub async fn pending_deliveries() -> Result<Vec<(Delivery, Duration)>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    sqlx::query(
+        r#"
+SELECT id, user_id, text, reply_markup, critical, attempts,
+    greatest(extract(epoch FROM next_attempt_at - now()), 0)::float8
+FROM outbox WHERE status = 'pending' ORDER BY id
+        "#,
+    )
+    .map(|row: PgRow| {
+        let delivery = Delivery {
+            id: row.get(0),
+            chat_id: row.get(1),
+            text: row.get(2),
+            reply_markup: row
+                .get::<Option<&str>, _>(3)
+                .and_then(|markup| serde_json::from_str(markup).ok()),
+            critical: row.get(4),
+            attempts: row.get(5),
+        };
+        (delivery, Duration::from_secs_f64(row.get(6)))
+    })
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `true` if message is still to be sent, i.e. it wasn't sent
+/// or discarded since its delivery was scheduled.
+pub async fn is_pending(id: i64) -> Result<bool, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    let pending = sqlx::query("SELECT true FROM outbox WHERE id = $1 AND status = 'pending'")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(pending.is_some())
+}
+
+pub async fn mark_sent(id: i64) -> Result<(), sqlx::Error> {
+    let pool = pg_pool().await;
+
+    sqlx::query("UPDATE outbox SET status = 'sent', sent_at = now() WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Records failed attempt to send a message, which is retried after `delay`.
+pub async fn schedule_retry(
+    id: i64,
+    attempts: i32,
+    delay: Duration,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    let pool = pg_pool().await;
+
+    sqlx::query(
+        r#"
+UPDATE outbox SET attempts = $2, next_attempt_at = now() + make_interval(secs => $3), last_error = $4
+WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(attempts)
+    .bind(delay.as_secs_f64())
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks message as permanently failed, it won't be retried.
+pub async fn mark_failed(id: i64, attempts: i32, error: &str) -> Result<(), sqlx::Error> {
+    let pool = pg_pool().await;
+
+    sqlx::query(
+        "UPDATE outbox SET status = 'failed', attempts = $2, last_error = $3 WHERE id = $1",
+    )
+    .bind(id)
+    .bind(attempts)
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns at most `limit` account updates since account transaction index ID,
+/// including the index itself.
+pub async fn account_updates_since(
+    index_id: i64,
+    limit: i64,
+) -> Result<Vec<(i64, String, i64, String)>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    let updates = sqlx::query(
+        r#"
+SELECT ati.id, ati.account, sm.timestamp, sm.summary::text FROM ati
+JOIN summaries AS sm ON ati.summary = sm.id
+WHERE ati.id >= $1 AND ati.account IN (SELECT DISTINCT ON (account) account FROM subscriptions)
+ORDER BY ati.id LIMIT $2
+        "#,
+    )
+    .bind(index_id)
+    .bind(limit)
+    .map(|row: PgRow| {
+        (
+            row.get(0),
+            row.get::<&[u8], _>(1).to_base58check(1),
+            row.get(2),
+            row.get(3),
+        )
+    })
+    .fetch_all(pool)
+    .await?;
+
+    Ok(updates)
+}
+
+/// Returns at most `limit` contract updates since contract transaction index ID,
+/// including the index itself.
+/// Updates of subscribed contracts and of contracts logging only CIS-2 token events are returned,
+/// since token events may concern subscribed accounts.
+pub async fn contract_updates_since(
+    index_id: i64,
+    limit: i64,
+) -> Result<Vec<(i64, ContractAddress, String)>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    let updates = sqlx::query(
+        r#"
+SELECT cti.id, cti.index, cti.subindex, sm.summary::text FROM cti
+JOIN summaries AS sm ON cti.summary = sm.id
+WHERE cti.id >= $1
+    AND (EXISTS (
+            SELECT true FROM contract_subscriptions AS cs
+            WHERE cs.index = cti.index AND cs.subindex = cti.subindex
+        )
+        OR logs_cis2_events(sm.summary, cti.index, cti.subindex))
+ORDER BY cti.id LIMIT $2
+        "#,
+    )
+    .bind(index_id)
+    .bind(limit)
+    .map(|row: PgRow| {
+        let index: i64 = row.get(1);
+        let subindex: i64 = row.get(2);
+        let contract = ContractAddress {
+            index: index as u64,
+            subindex: subindex as u64,
+        };
+        (row.get(0), contract, row.get(3))
+    })
+    .fetch_all(pool)
+    .await?;
+
+    Ok(updates)
+}
+
+/// Returns at most `limit` summaries of chain update transactions since summary ID,
+/// including the summary itself.
+pub async fn chain_updates_since(id: i64, limit: i64) -> Result<Vec<(i64, String)>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    let updates = sqlx::query(
+        r#"
+SELECT id, summary::text FROM summaries
+WHERE id >= $1 AND summary->'Left'->'type'->>'type' = 'updateTransaction'
+ORDER BY id LIMIT $2
+        "#,
+    )
+    .bind(id)
+    .bind(limit)
+    .map(|row: PgRow| (row.get(0), row.get(1)))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(updates)
+}
+
+/// Returns at most `limit` summaries of pool rewards and delegation transactions since summary ID,
+/// including the summary itself.
+pub async fn pool_updates_since(id: i64, limit: i64) -> Result<Vec<(i64, String)>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    let updates = sqlx::query(
+        r#"
+SELECT id, summary::text FROM summaries
+WHERE id >= $1
+    AND ((summary->'Right'->>'tag' = 'PaydayPoolReward'
+            AND EXISTS (
+                SELECT true FROM subscriptions
+                WHERE account_index = (summary->'Right'->>'poolOwner')::bigint
+            ))
+        OR (summary->'Left'->'type'->>'contents' = 'configureDelegation'
+            AND summary->'Left'->'result'->>'outcome' = 'success'))
+ORDER BY id LIMIT $2
+        "#,
+    )
+    .bind(id)
+    .bind(limit)
+    .map(|row: PgRow| (row.get(0), row.get(1)))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(updates)
+}
+
+/// Sets baker pool of a delegator, `None` for passive or no delegation.
+/// Returns the previous pool of the delegator, the same pool if the summary was already handled.
+pub async fn set_delegator_pool(
+    delegator: &AccountAddress,
+    baker_id: Option<u64>,
+    summary_id: i64,
+) -> Result<Option<u64>, sqlx::Error> {
+    let pool = pg_pool().await;
+    let mut tx = pool.begin().await?;
+    let baker_id = baker_id.map(|id| id as i64);
+
+    let row: Option<(Option<i64>, Option<i64>, i64)> = sqlx::query(
+        r#"
+SELECT baker_id, previous_baker_id, summary_id FROM pool_delegators
+WHERE delegator = $1 FOR UPDATE
+        "#,
+    )
+    .bind(delegator.to_bytes())
+    .map(|row: PgRow| (row.get(0), row.get(1), row.get(2)))
+    .fetch_optional(&mut tx)
+    .await?;
+
+    let previous = match row {
+        // The summary is replayed, its pool change is reported again
+        Some((_, previous, id)) if id == summary_id => previous,
+        // A later summary already set the pool
+        Some((_, _, id)) if id > summary_id => baker_id,
+        Some((current, _, _)) => current,
+        None => None,
+    };
+
+    sqlx::query(
+        r#"
+INSERT INTO pool_delegators (delegator, baker_id, previous_baker_id, summary_id)
+VALUES ($1, $2, $3, $4)
+ON CONFLICT (delegator) DO UPDATE
+SET baker_id = $2, previous_baker_id = $3, summary_id = $4
+WHERE pool_delegators.summary_id <= $4
+        "#,
+    )
+    .bind(delegator.to_bytes())
+    .bind(baker_id)
+    .bind(previous)
+    .bind(summary_id)
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(previous.map(|id| id as u64))
+}
+
+/// Stores pending releases of a scheduled transfer to account.
+/// Release timestamps are given in milliseconds.
+pub async fn add_scheduled_releases(
+    account: &AccountAddress,
+    tx_hash: &str,
+    releases: &[(u64, Amount)],
+) -> Result<(), sqlx::Error> {
+    let pool = pg_pool().await;
+
+    for (timestamp, amount) in releases {
+        sqlx::query(
+            r#"
+INSERT INTO scheduled_releases (account, tx_hash, release_time, amount)
+VALUES ($1, $2, to_timestamp($3::bigint / 1000.0), $4)
+ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(account.to_bytes())
+        .bind(tx_hash)
+        .bind(*timestamp as i64)
+        .bind(amount.micro_ccd() as i64)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Returns releases of scheduled transfers which became available with their IDs.
+/// A release is removed once the reminder about it is stored, see `claim`.
+pub async fn due_releases() -> Result<Vec<(i64, AccountAddress, String, Amount)>, sqlx::Error> {
+    let pool = pg_pool().await;
+
+    let releases = sqlx::query(
+        r#"
+SELECT id, account, tx_hash, amount FROM scheduled_releases
+WHERE release_time <= now() ORDER BY release_time, id
+        "#,
+    )
+    .map(|row: PgRow| {
+        let account = row.get::<&[u8], _>(1).to_base58check(1);
+        let amount: i64 = row.get(3);
+        (
+            row.get(0),
+            AccountAddress::new(account),
+            row.get(2),
+            Amount::from(amount as u64),
+        )
+    })
+    .fetch_all(pool)
+    .await?;
+
+    Ok(releases)
+}
+
+/// Removes rows claimed by a message which is being stored.
+/// Returns `false` if they were removed already, i.e. the message was stored before.
+pub async fn claim(conn: &mut PgConnection, claim: &Claim) -> Result<bool, sqlx::Error> {
+    match claim {
+        Claim::Release(id) => {
+            let removed = sqlx::query("DELETE FROM scheduled_releases WHERE id = $1")
+                .bind(id)
+                .execute(conn)
+                .await?
+                .rows_affected();
+            Ok(removed > 0)
+        }
+        Claim::Digest {
+            user_id,
+            up_to,
+            count,
+        } => {
+            // Events are removed by another digest stored before, or the user was deactivated
+            let removed = sqlx::query("DELETE FROM digest_events WHERE user_id = $1 AND id <= $2")
+                .bind(user_id)
+                .bind(up_to)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+            if removed != *count as u64 {
+                return Ok(false);
+            }
+
+            sqlx::query("UPDATE users SET last_digest_at = now() WHERE user_id = $1")
+                .bind(user_id)
+                .execute(conn)
+                .await?;
+            Ok(true)
+        }
+        Claim::DeferredMessage(id) => {
+            let removed = sqlx::query("DELETE FROM deferred_messages WHERE id = $1")
+                .bind(id)
+                .execute(conn)
+                .await?
+                .rows_affected();
+            Ok(removed > 0)
+        }
+    }
 }
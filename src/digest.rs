@@ -0,0 +1,77 @@
+//! Digests of events accumulated for users who don't receive notifications immediately.
+use crate::types::{AccountAddress, DeliveryMode, DigestSettings, Direction};
+use crate::{
+    db::{self, DigestEntry},
+    sender::Message,
+    utils::*,
+};
+use chrono::Utc;
+use log::*;
+use std::time::Duration;
+use tokio::{sync::mpsc::Sender, time};
+
+/// How often digest schedules are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically sends digests to users whose scheduled time has come.
+pub async fn handle_digests(tx: Sender<Message>) {
+    let mut interval = time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let pending = match db::pending_digests().await {
+            Ok(pending) => pending,
+            Err(err) => {
+                error!("{}", err);
+                continue;
+            }
+        };
+
+        let now = Utc::now();
+        for settings in pending.iter().filter(|settings| settings.is_due(now)) {
+            // Events are removed and the digest is marked as sent when it's stored
+            match db::digest(settings.user_id).await {
+                Ok((digest, claim)) if !digest.is_empty() => {
+                    let text = format_digest(settings, &digest);
+                    let message =
+                        Message::without_index(vec![settings.user_id], text).with_claim(claim);
+                    tx.send(message).await.ok();
+                }
+                Ok(_) => (),
+                Err(err) => error!("{}", err),
+            }
+        }
+    }
+}
+
+/// Formats number of events and total amount for each account, category and direction
+/// of transfers, `digest` is expected to be ordered by account.
+fn format_digest(settings: &DigestSettings, digest: &[DigestEntry]) -> String {
+    let mut text = match settings.delivery_mode {
+        DeliveryMode::Hourly => "📰 Hourly digest".to_string(),
+        DeliveryMode::Daily => "📰 Daily digest".to_string(),
+        DeliveryMode::Immediate => "📰 Events since the last digest".to_string(),
+    };
+
+    let mut last_account: Option<&AccountAddress> = None;
+    for (account, category, direction, count, amount) in digest {
+        if last_account != Some(account) {
+            text.push_str(&format!("\n\n{}", format_account_address(account, true)));
+            last_account = Some(account);
+        }
+
+        let events = if *count == 1 { "event" } else { "events" };
+        text.push_str(&format!("\n• {}", category));
+        match direction {
+            Some(Direction::Both) => text.push_str(" (to self)"),
+            Some(direction) => text.push_str(&format!(" ({})", direction)),
+            None => {}
+        }
+        text.push_str(&format!(": {} {}", count, events));
+        if let Some(amount) = amount {
+            text.push_str(&format!(", {} CCD", amount));
+        }
+    }
+    text
+}
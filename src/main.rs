@@ -1,9 +1,17 @@
+mod address_book;
+mod cbor;
+mod cis2;
 mod command;
 mod db;
+mod digest;
 mod listener;
+mod quiet;
+mod rate_limit;
+mod releases;
 mod repl;
 mod rpc;
 mod sender;
+mod settings;
 mod states;
 mod transitions;
 mod types;
@@ -71,15 +79,33 @@ async fn run() {
     info!("Loading subscriptions");
     db::load_subscriptions(pool).await.unwrap();
 
+    // Look up indices of subscribed accounts, which identify baker pools they may own
+    tokio::spawn(index_accounts());
+
     // Spawn Telegram messages sender
     let (tx, rx) = mpsc::channel(2048);
     tokio::spawn(sender::handle_messages(rx, bot.clone()));
 
-    // Find and process missing account updates
-    updates::process_updates_since_last_ati(tx.clone()).await;
+    // Remove old entries of the outbox
+    tokio::spawn(sender::cleanup_outbox());
+
+    // Remind about releases of scheduled transfers
+    tokio::spawn(releases::handle_releases(tx.clone()));
+
+    // Send digests of accumulated events on schedule
+    tokio::spawn(digest::handle_digests(tx.clone()));
+
+    // Send messages deferred during quiet hours
+    tokio::spawn(quiet::handle_deferred(tx.clone()));
 
-    // Handle Concordium account updates via PostgreSQL pub/sub channel
-    tokio::spawn(updates::handle_updates(tx));
+    // Catch up on missing account, contract and chain updates,
+    // then handle Concordium updates via PostgreSQL pub/sub channels
+    tokio::spawn(async move {
+        if let Err(err) = updates::handle_updates(tx).await {
+            error!("Handling updates failed: {}", err);
+            std::process::exit(1);
+        }
+    });
 
     if let Some(host) = std::env::var("TELEGRAM_WEBHOOK_HOST").ok() {
         info!("Receiving updates via webhook on {}", host);
@@ -91,3 +117,26 @@ async fn run() {
         repl::dialogue_repl(bot, listener).await;
     };
 }
+
+/// Stores indices of subscribed accounts that were not looked up on subscription.
+async fn index_accounts() {
+    let accounts = match db::unindexed_accounts().await {
+        Ok(accounts) => accounts,
+        Err(err) => {
+            error!("Loading accounts without index failed: {}", err);
+            return;
+        }
+    };
+
+    for account in accounts {
+        match rpc::get_account_index(account.address()).await {
+            Ok(Some(index)) => {
+                if let Err(err) = db::set_account_index(&account, index).await {
+                    error!("Storing index of account {} failed: {}", account, err);
+                }
+            }
+            Ok(None) => warn!("Account {} not found", account),
+            Err(err) => error!("Looking up index of account {} failed: {}", account, err),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Delivery of messages deferred during quiet hours.
+use crate::types::QuietMode;
+use crate::{
+    db, redis_cm,
+    sender::{Claim, Message},
+};
+use chrono::Utc;
+use log::*;
+use std::time::Duration;
+use tokio::{sync::mpsc::Sender, time};
+
+/// How often deferred messages are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically sends deferred messages to users whose quiet hours have ended.
+pub async fn handle_deferred(tx: Sender<Message>) {
+    let mut cm = redis_cm().await.clone();
+    let mut interval = time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let user_ids = match db::deferred_user_ids().await {
+            Ok(user_ids) => user_ids,
+            Err(err) => {
+                error!("{}", err);
+                continue;
+            }
+        };
+
+        for user_id in user_ids {
+            let quiet_hours = db::quiet_hours(&mut cm, user_id).await.unwrap();
+            if let Some(quiet_hours) = quiet_hours {
+                if quiet_hours.mode == QuietMode::Defer && quiet_hours.is_active(Utc::now()) {
+                    continue;
+                }
+            }
+
+            // A message is removed from deferred ones when it's stored in the outbox
+            match db::deferred_messages(user_id).await {
+                Ok(messages) => {
+                    for (id, text, reply_markup) in messages {
+                        let mut message = Message::without_index(vec![user_id], text)
+                            .with_claim(Claim::DeferredMessage(id));
+                        if let Some(reply_markup) = reply_markup {
+                            message = message.with_markup(reply_markup);
+                        }
+                        tx.send(message).await.ok();
+                    }
+                }
+                Err(err) => error!("{}", err),
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Limits of sending messages via Telegram Bot API.
+//! More info: https://core.telegram.org/bots/faq#my-bot-is-hitting-limits-how-do-i-avoid-this
+use std::cmp;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Maximum number of messages sent per second to all chats.
+const GLOBAL_LIMIT: u32 = 30;
+
+/// Minimum interval between messages sent to a private chat.
+const PRIVATE_CHAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Minimum interval between messages sent to a group, i.e. 20 messages per minute.
+const GROUP_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Token bucket refilled with one token per `interval` up to `capacity` tokens.
+struct TokenBucket {
+    capacity: u32,
+    interval: Duration,
+    tokens: u32,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: u32, interval: Duration, now: Instant) -> Self {
+        Self {
+            capacity,
+            interval,
+            tokens: capacity,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if self.tokens >= self.capacity {
+            self.updated_at = cmp::max(self.updated_at, now);
+            return;
+        }
+
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        let new_tokens = elapsed.as_nanos() / self.interval.as_nanos();
+        let new_tokens = cmp::min(new_tokens, self.capacity as u128) as u32;
+        if new_tokens > 0 {
+            self.tokens = cmp::min(self.tokens + new_tokens, self.capacity);
+            self.updated_at += self.interval * new_tokens;
+        }
+    }
+
+    /// Returns when the next token is available.
+    fn available_at(&mut self, now: Instant) -> Instant {
+        self.refill(now);
+        if self.tokens > 0 {
+            now
+        } else {
+            self.updated_at + self.interval
+        }
+    }
+
+    fn take(&mut self, now: Instant) {
+        self.refill(now);
+        self.tokens = self.tokens.saturating_sub(1);
+    }
+
+    /// Empties the bucket, so the next token is available an interval after `until`.
+    fn pause(&mut self, until: Instant) {
+        self.tokens = 0;
+        self.updated_at = cmp::max(self.updated_at, until);
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.capacity
+    }
+}
+
+/// Global and per-chat limits of sending messages.
+pub struct RateLimiter {
+    global: TokenBucket,
+    chats: HashMap<i64, TokenBucket>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        // Messages are spread evenly, a burst on top of the refill rate would exceed
+        // the limit within a second. The interval is rounded up for the same reason.
+        let interval = Duration::from_nanos(1_000_000_000u64.div_ceil(GLOBAL_LIMIT.into()));
+        Self {
+            global: TokenBucket::new(1, interval, Instant::now()),
+            chats: HashMap::new(),
+        }
+    }
+}
+
+impl RateLimiter {
+    fn chat(&mut self, chat_id: i64, now: Instant) -> &mut TokenBucket {
+        self.chats.entry(chat_id).or_insert_with(|| {
+            // IDs of groups and channels are negative
+            let interval = if chat_id < 0 {
+                GROUP_INTERVAL
+            } else {
+                PRIVATE_CHAT_INTERVAL
+            };
+            TokenBucket::new(1, interval, now)
+        })
+    }
+
+    /// Returns when a message can be sent to any chat.
+    pub fn global_available_at(&mut self, now: Instant) -> Instant {
+        self.global.available_at(now)
+    }
+
+    /// Returns when a message can be sent to the chat, not taking the global limit into account.
+    pub fn chat_available_at(&mut self, chat_id: i64, now: Instant) -> Instant {
+        self.chat(chat_id, now).available_at(now)
+    }
+
+    /// Counts a message sent to the chat.
+    pub fn acquire(&mut self, chat_id: i64, now: Instant) {
+        self.global.take(now);
+        self.chat(chat_id, now).take(now);
+    }
+
+    /// Holds messages to the chat until `until`, e.g. after Telegram responded with `retry_after`.
+    pub fn pause(&mut self, chat_id: i64, until: Instant) {
+        let now = Instant::now();
+        self.chat(chat_id, now).pause(until);
+    }
+
+    /// Forgets chats which can be sent to without waiting.
+    pub fn cleanup(&mut self, now: Instant) {
+        self.chats.retain(|_, bucket| !bucket.is_full(now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_limit_holds_within_any_second() {
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+        let mut sent = Vec::new();
+        let mut now = start;
+        for chat_id in 0..100 {
+            now = limiter.global_available_at(now);
+            limiter.acquire(chat_id, now);
+            sent.push(now);
+        }
+
+        for (i, &from) in sent.iter().enumerate() {
+            let count = sent[i..]
+                .iter()
+                .take_while(|&&at| at < from + Duration::from_secs(1))
+                .count();
+            assert!(
+                count <= GLOBAL_LIMIT as usize,
+                "{} sent within a second",
+                count
+            );
+        }
+        assert!(sent[GLOBAL_LIMIT as usize] < start + Duration::from_millis(1100));
+    }
+}
@@ -0,0 +1,62 @@
+//! Reminders about releases of scheduled transfers.
+use crate::types::{Category, DigestEvent, Direction};
+use crate::{
+    db, redis_cm,
+    sender::{Claim, Message},
+    utils::*,
+};
+use log::*;
+use std::time::Duration;
+use tokio::{sync::mpsc::Sender, time};
+
+/// How often pending releases are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically notifies subscribers about amounts of scheduled transfers which became available.
+pub async fn handle_releases(tx: Sender<Message>) {
+    let mut cm = redis_cm().await.clone();
+    let mut interval = time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let releases = match db::due_releases().await {
+            Ok(releases) => releases,
+            Err(err) => {
+                error!("{}", err);
+                continue;
+            }
+        };
+
+        // A release is removed when the reminder is stored, even if nobody receives it
+        for (id, account, tx_hash, amount) in releases {
+            let user_ids: Vec<i64> = db::subscribers(&mut cm, account.address())
+                .await
+                .unwrap()
+                .iter()
+                .filter(|s| {
+                    s.direction.accepts(Direction::Incoming)
+                        && s.accepts(Category::ScheduledTransfers)
+                })
+                .map(|s| s.user_id)
+                .collect();
+
+            let text = format!(
+                "⏰ {} CCD from schedule {} became available\nAccount: {}",
+                amount,
+                format_txhash(&tx_hash),
+                format_account_address(&account, true)
+            );
+            let event = DigestEvent {
+                account,
+                category: Category::ScheduledTransfers,
+                direction: Some(Direction::Incoming),
+                amount: Some(amount),
+            };
+            let message = Message::without_index(user_ids, text)
+                .with_digest_event(event)
+                .with_claim(Claim::Release(id));
+            tx.send(message).await.ok();
+        }
+    }
+}
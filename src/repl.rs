@@ -1,13 +1,20 @@
-use crate::states::Dialogue;
+use crate::states::{Dialogue, ReceiveNameState};
 use crate::utils::env;
-use crate::BotType;
+use crate::{address_book, settings, BotType};
+use futures::StreamExt;
+use log::*;
+use std::sync::Arc;
 use teloxide::{
     dispatching::dialogue::{serializer::Json, RedisStorage, Storage},
     dispatching::update_listeners::UpdateListener,
+    dispatching::DispatcherHandlerRx,
     prelude::*,
+    types::CallbackQuery,
+    utils::html,
     RequestError,
 };
 use thiserror::Error;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 
 type StorageError = <RedisStorage<Json> as Storage<Dialogue>>::Error;
 type In = DialogueWithCx<BotType, Message, Dialogue, StorageError>;
@@ -25,6 +32,8 @@ where
     UListener: UpdateListener<ListenerE>,
     ListenerE: std::fmt::Debug,
 {
+    let storage = RedisStorage::open(env("REDIS_URL"), Json).await.unwrap();
+
     Dispatcher::new(bot)
         .messages_handler(DialogueDispatcher::with_storage(
             |DialogueWithCx { cx, dialogue }: In| async move {
@@ -33,8 +42,19 @@ where
                     .await
                     .expect("Something wrong with the bot!")
             },
-            RedisStorage::open(env("REDIS_URL"), Json).await.unwrap(),
+            storage.clone(),
         ))
+        .callback_queries_handler(move |rx: DispatcherHandlerRx<BotType, CallbackQuery>| {
+            let storage = storage.clone();
+            UnboundedReceiverStream::new(rx).for_each_concurrent(None, move |cx| {
+                let storage = storage.clone();
+                async move {
+                    if let Err(err) = handle_callback_query(cx, storage).await {
+                        error!("{}", err);
+                    }
+                }
+            })
+        })
         .setup_ctrlc_handler()
         .dispatch_with_listener(
             listener,
@@ -55,3 +75,40 @@ async fn handle_message(
         Some(ans) => dialogue.react(cx, ans).await,
     }
 }
+
+async fn handle_callback_query(
+    cx: UpdateWithCx<BotType, CallbackQuery>,
+    storage: Arc<RedisStorage<Json>>,
+) -> Result<(), Error> {
+    let query = &cx.update;
+    let address = query
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix(address_book::SAVE_PREFIX));
+
+    match address {
+        // Ask for a name of the address to be saved, the answer is handled by the dialogue
+        Some(address) => {
+            let chat_id = match &query.message {
+                Some(message) => message.chat.id,
+                None => query.from.id,
+            };
+            let text = format!(
+                "OK, send me a name for {}, or - to remove it from the address book",
+                html::escape(address)
+            );
+            cx.requester.answer_callback_query(query.id.clone()).await?;
+            cx.requester.send_message(chat_id, text).await?;
+
+            let state = ReceiveNameState {
+                address: address.to_string(),
+            };
+            storage
+                .update_dialogue(chat_id, Dialogue::from(state))
+                .await?;
+        }
+        None => settings::handle_callback_query(cx).await?,
+    }
+
+    Ok(())
+}
@@ -16,7 +16,8 @@ fn parse_response(response: Response<JsonResponse>) -> Result<Value, serde_json:
     Ok(serde_json::from_str(&json)?)
 }
 
-pub async fn get_account_balance(address: &str) -> Result<Option<String>, Error> {
+/// Fetches info of account at the best block, `Null` if the account doesn't exist.
+async fn get_account_info(address: &str) -> Result<Value, Error> {
     let uri: Uri = env("CONCORDIUM_GRPC_URL").parse().unwrap();
     let channel = Channel::builder(uri).connect().await?;
 
@@ -39,7 +40,11 @@ pub async fn get_account_balance(address: &str) -> Result<Option<String>, Error>
     });
 
     let resp = client.get_account_info(request).await?;
-    let json = parse_response(resp)?;
+    Ok(parse_response(resp)?)
+}
+
+pub async fn get_account_balance(address: &str) -> Result<Option<String>, Error> {
+    let json = get_account_info(address).await?;
 
     if json.is_object() {
         Ok(json["accountAmount"].as_str().map(ToOwned::to_owned))
@@ -47,3 +52,9 @@ pub async fn get_account_balance(address: &str) -> Result<Option<String>, Error>
         Ok(None)
     }
 }
+
+/// Returns index of account, which is also ID of the baker pool the account may own.
+pub async fn get_account_index(address: &str) -> Result<Option<u64>, Error> {
+    let json = get_account_info(address).await?;
+    Ok(json["accountIndex"].as_u64())
+}
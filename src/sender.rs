@@ -1,36 +1,724 @@
-use crate::{redis_cm, BotType};
+use crate::rate_limit::RateLimiter;
+use crate::types::{DeliveryMode, DigestEvent, QuietMode, TransactionIndex};
+use crate::{db, pg_pool, redis_cm, utils::*, BotType};
+use chrono::Utc;
 use log::*;
-use redis::AsyncCommands;
+use redis::{aio::ConnectionManager, RedisError};
+use sqlx::PgConnection;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use teloxide::payloads::SendMessageSetters;
 use teloxide::prelude::*;
-use tokio::sync::mpsc::Receiver;
+use teloxide::types::InlineKeyboardMarkup;
+use teloxide::{ApiError, RequestError};
+use thiserror::Error;
+use tokio::sync::mpsc::{self, Receiver, UnboundedSender};
+use tokio::time::{self, Instant};
+
+/// How long the sender waits for new messages when there is nothing to send.
+const IDLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of attempts to send a message before it's marked as failed.
+const MAX_ATTEMPTS: i32 = 10;
+
+/// Delay before the first retry, doubled with each failed attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Maximum delay between retries.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+/// Maximum delay between attempts to store a message.
+const MAX_STORE_DELAY: Duration = Duration::from_secs(60);
+
+/// How often old entries are removed from the outbox.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How long sent messages are kept in the outbox.
+const SENT_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// How long failed messages are kept in the outbox for investigation.
+const FAILED_RETENTION: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// Number of users whose subscriptions were removed because their chats became unavailable.
+static DEACTIVATED_USERS: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Debug)]
 pub struct Message {
-    index_id: i64,
+    /// Index of the handled transaction, `None` for messages not caused by a transaction.
+    index: Option<TransactionIndex>,
+    /// Number of the message among messages about the transaction.
+    seq: i32,
     user_ids: Vec<i64>,
     text: String,
+    reply_markup: Option<InlineKeyboardMarkup>,
+    /// Event the message is about, accumulated instead for users receiving digests.
+    digest_event: Option<DigestEvent>,
+    claim: Option<Claim>,
+}
+
+/// Rows a message is built from, removed in the same database transaction the message
+/// is stored in. The message is skipped if they were removed already, i.e. it was stored
+/// before, so it's neither lost nor sent twice.
+#[derive(Debug)]
+pub enum Claim {
+    /// ID of a release of a scheduled transfer.
+    Release(i64),
+    /// Digest events of a Telegram user with IDs up to `up_to`, of which there are `count`.
+    Digest {
+        user_id: i64,
+        up_to: i64,
+        count: i64,
+    },
+    /// ID of a message deferred during quiet hours.
+    DeferredMessage(i64),
 }
 
 impl Message {
-    pub fn new(index_id: i64, user_ids: Vec<i64>, text: String) -> Self {
+    pub fn new(index: TransactionIndex, seq: i32, user_ids: Vec<i64>, text: String) -> Self {
         Self {
-            index_id,
+            index: Some(index),
+            seq,
             user_ids,
             text,
+            reply_markup: None,
+            digest_event: None,
+            claim: None,
+        }
+    }
+
+    /// Message without recipients, which only advances the progress to `index`.
+    pub fn progress(index: TransactionIndex) -> Self {
+        Self::new(index, 0, Vec::new(), String::new())
+    }
+
+    pub fn without_index(user_ids: Vec<i64>, text: String) -> Self {
+        Self {
+            index: None,
+            seq: 0,
+            user_ids,
+            text,
+            reply_markup: None,
+            digest_event: None,
+            claim: None,
+        }
+    }
+
+    pub fn with_markup(mut self, reply_markup: InlineKeyboardMarkup) -> Self {
+        self.reply_markup = Some(reply_markup);
+        self
+    }
+
+    pub fn with_digest_event(mut self, digest_event: DigestEvent) -> Self {
+        self.digest_event = Some(digest_event);
+        self
+    }
+
+    pub fn with_claim(mut self, claim: Claim) -> Self {
+        self.claim = Some(claim);
+        self
+    }
+
+    /// Returns `true` if the message is about a critical event, which bypasses quiet hours.
+    fn is_critical(&self) -> bool {
+        match &self.digest_event {
+            Some(event) => event.category.is_critical(),
+            None => false,
+        }
+    }
+}
+
+/// Message to a single chat stored in the outbox.
+pub struct Delivery {
+    /// ID of the outbox entry.
+    pub id: i64,
+    pub chat_id: i64,
+    pub text: String,
+    pub reply_markup: Option<InlineKeyboardMarkup>,
+    /// Sent with sound even during quiet hours.
+    pub critical: bool,
+    /// Number of failed attempts to send the message.
+    pub attempts: i32,
+}
+
+#[derive(Debug, Error)]
+enum StoreError {
+    #[error("error from PostgreSQL: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+    #[error("error from Redis: {0}")]
+    RedisError(#[from] RedisError),
+}
+
+impl StoreError {
+    /// Returns `true` for errors which may not happen on the next attempt,
+    /// e.g. a lost connection, as opposed to a message the database rejects.
+    fn is_transient(&self) -> bool {
+        match self {
+            StoreError::DatabaseError(err) => match err {
+                sqlx::Error::Io(_)
+                | sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::WorkerCrashed => true,
+                // Connection exceptions, transaction rollbacks such as deadlocks,
+                // insufficient resources and operator intervention like a shutdown
+                sqlx::Error::Database(err) => err.code().is_some_and(|code| {
+                    ["08", "40", "53", "57"].contains(&&code[..code.len().min(2)])
+                }),
+                _ => false,
+            },
+            StoreError::RedisError(err) => {
+                err.is_io_error()
+                    || err.is_connection_refusal()
+                    || err.is_connection_dropped()
+                    || err.is_timeout()
+            }
+        }
+    }
+}
+
+/// Result of sending a message, reported back by a spawned request.
+struct Sent {
+    delivery: Delivery,
+    result: Result<(), RequestError>,
+}
+
+/// Messages waiting to be sent. Chats take turns, so users subscribed
+/// to busy accounts don't delay notifications of other users.
+#[derive(Default)]
+struct Queue {
+    chats: HashMap<i64, VecDeque<Delivery>>,
+    /// IDs of chats having queued messages in the order they're served.
+    turns: VecDeque<i64>,
+}
+
+impl Queue {
+    fn push(&mut self, delivery: Delivery) {
+        let chat_id = delivery.chat_id;
+        let messages = self.chats.entry(chat_id).or_default();
+        if messages.is_empty() {
+            self.turns.push_back(chat_id);
+        }
+        messages.push_back(delivery);
+    }
+
+    /// Puts back a message which has to be resent before the other messages to the chat.
+    fn push_front(&mut self, delivery: Delivery) {
+        let chat_id = delivery.chat_id;
+        let messages = self.chats.entry(chat_id).or_default();
+        if messages.is_empty() {
+            self.turns.push_back(chat_id);
+        }
+        messages.push_front(delivery);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    /// Drops queued messages to the chat.
+    fn remove_chat(&mut self, chat_id: i64) {
+        if self.chats.remove(&chat_id).is_some() {
+            self.turns.retain(|id| *id != chat_id);
+        }
+    }
+
+    /// Takes the next message which can be sent without exceeding rate limits.
+    /// Otherwise returns when the next message can be sent, `None` if the queue is empty.
+    fn pop(
+        &mut self,
+        limiter: &mut RateLimiter,
+        now: Instant,
+    ) -> Result<Delivery, Option<Instant>> {
+        let global_available_at = limiter.global_available_at(now);
+        if global_available_at > now {
+            return Err(Some(global_available_at));
         }
+
+        let mut available_at: Option<Instant> = None;
+        for turn in 0..self.turns.len() {
+            let chat_id = self.turns[turn];
+            let chat_available_at = limiter.chat_available_at(chat_id, now);
+            if chat_available_at > now {
+                available_at = Some(available_at.map_or(chat_available_at, |available_at| {
+                    available_at.min(chat_available_at)
+                }));
+                continue;
+            }
+
+            self.turns.remove(turn);
+            let messages = self.chats.get_mut(&chat_id).unwrap();
+            let delivery = messages.pop_front().unwrap();
+            if messages.is_empty() {
+                self.chats.remove(&chat_id);
+            } else {
+                self.turns.push_back(chat_id);
+            }
+
+            limiter.acquire(chat_id, now);
+            return Ok(delivery);
+        }
+
+        Err(available_at)
     }
 }
 
+/// Sends messages from the outbox respecting Telegram rate limits.
+/// Messages failed due to transient errors are retried with exponential backoff.
 pub async fn handle_messages(mut rx: Receiver<Message>, bot: BotType) {
     let mut conn = redis_cm().await.clone();
+    let (sent_tx, mut sent_rx) = mpsc::unbounded_channel();
+    let (retry_tx, mut retry_rx) = mpsc::unbounded_channel();
+    let mut queue = Queue::default();
+    let mut limiter = RateLimiter::default();
+
+    // Resume deliveries which weren't sent before restart
+    match db::pending_deliveries().await {
+        Ok(deliveries) => {
+            info!("Resuming {} pending deliveries", deliveries.len());
+            for (delivery, delay) in deliveries {
+                retry_later(delivery, delay, retry_tx.clone());
+            }
+        }
+        Err(err) => error!("{}", err),
+    }
+
+    loop {
+        let now = Instant::now();
+        let wake_at = loop {
+            match queue.pop(&mut limiter, now) {
+                Ok(delivery) => send(&bot, delivery, sent_tx.clone()),
+                Err(available_at) => break available_at.unwrap_or(now + IDLE_INTERVAL),
+            }
+        };
+        if queue.is_empty() {
+            limiter.cleanup(now);
+        }
+
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => enqueue(&mut conn, msg, &mut queue).await,
+                None => break,
+            },
+            Some(sent) = sent_rx.recv() => {
+                handle_sent(sent, &mut queue, &mut limiter, &retry_tx).await;
+            }
+            Some(delivery) = retry_rx.recv() => requeue(delivery, &mut queue).await,
+            _ = time::sleep_until(wake_at) => (),
+        }
+    }
+}
+
+/// Periodically removes sent and failed messages from the outbox after their retention period.
+pub async fn cleanup_outbox() {
+    let mut interval = time::interval(CLEANUP_INTERVAL);
 
-    while let Some(msg) = rx.recv().await {
-        for user_id in msg.user_ids {
-            match bot.send_message(user_id, &msg.text).await {
-                Ok(_) => debug!("Message sent to Telegram ID {}", user_id),
+    loop {
+        interval.tick().await;
+
+        match db::remove_old_deliveries(SENT_RETENTION, FAILED_RETENTION).await {
+            Ok(count) if count > 0 => info!("Removed {} old messages from the outbox", count),
+            Ok(_) => {}
+            Err(err) => error!("{}", err),
+        }
+    }
+}
+
+async fn handle_sent(
+    sent: Sent,
+    queue: &mut Queue,
+    limiter: &mut RateLimiter,
+    retry_tx: &UnboundedSender<Delivery>,
+) {
+    let mut delivery = sent.delivery;
+    let chat_id = delivery.chat_id;
+
+    let result = match sent.result {
+        Ok(()) => {
+            debug!("Message sent to Telegram ID {}", chat_id);
+            db::mark_sent(delivery.id).await
+        }
+        Err(RequestError::RetryAfter(secs)) => {
+            warn!(
+                "Retrying message to Telegram ID {} after {}s",
+                chat_id, secs
+            );
+            let retry_after = Duration::from_secs(secs.max(1) as u64);
+            limiter.pause(chat_id, Instant::now() + retry_after);
+            queue.push_front(delivery);
+            Ok(())
+        }
+        Err(err) if is_transient(&err) && delivery.attempts + 1 < MAX_ATTEMPTS => {
+            delivery.attempts += 1;
+            let delay = retry_delay(delivery.attempts);
+            warn!(
+                "Retrying message to Telegram ID {} in {:?}: {}",
+                chat_id, delay, err
+            );
+            let result =
+                db::schedule_retry(delivery.id, delivery.attempts, delay, &err.to_string()).await;
+            retry_later(delivery, delay, retry_tx.clone());
+            result
+        }
+        Err(err) if is_chat_unavailable(&err) => {
+            queue.remove_chat(chat_id);
+            let result =
+                db::mark_failed(delivery.id, delivery.attempts + 1, &err.to_string()).await;
+            match db::deactivate_user(chat_id).await {
+                Ok(()) => {
+                    let count = DEACTIVATED_USERS.fetch_add(1, Ordering::Relaxed) + 1;
+                    info!(
+                        "Removed subscriptions of Telegram ID {} ({}), {} users deactivated since start",
+                        chat_id, err, count
+                    );
+                }
                 Err(err) => error!("{}", err),
             }
+            result
         }
-        let _: () = conn.set("ati:latest", msg.index_id).await.unwrap();
+        Err(err) => {
+            error!("Failed to send message to Telegram ID {}: {}", chat_id, err);
+            db::mark_failed(delivery.id, delivery.attempts + 1, &err.to_string()).await
+        }
+    };
+
+    if let Err(err) = result {
+        error!("{}", err);
+    }
+}
+
+/// Queues delivery to be retried unless it was discarded meanwhile,
+/// e.g. its chat became unavailable while the retry was scheduled.
+async fn requeue(delivery: Delivery, queue: &mut Queue) {
+    match db::is_pending(delivery.id).await {
+        Ok(true) => queue.push(delivery),
+        Ok(false) => debug!(
+            "Dropped retry of discarded message to Telegram ID {}",
+            delivery.chat_id
+        ),
+        Err(err) => {
+            // The status is unknown, sending to an unavailable chat only fails again
+            error!("{}", err);
+            queue.push(delivery);
+        }
+    }
+}
+
+/// Returns `true` for errors meaning messages can't be sent to the chat anymore,
+/// e.g. the user blocked the bot or deleted their account.
+fn is_chat_unavailable(err: &RequestError) -> bool {
+    matches!(
+        err,
+        RequestError::ApiError {
+            kind: ApiError::BotBlocked
+                | ApiError::BotKicked
+                | ApiError::BotKickedFromSupergroup
+                | ApiError::UserDeactivated
+                | ApiError::ChatNotFound
+                | ApiError::CantInitiateConversation,
+            ..
+        }
+    )
+}
+
+/// Returns `true` for errors which may not happen on the next attempt.
+fn is_transient(err: &RequestError) -> bool {
+    match err {
+        RequestError::NetworkError(_) | RequestError::RetryAfter(_) => true,
+        RequestError::ApiError { status_code, .. } => status_code.is_server_error(),
+        _ => false,
+    }
+}
+
+/// Returns delay before the next attempt after the number of failed `attempts`.
+fn retry_delay(attempts: i32) -> Duration {
+    let factor = 2u32.saturating_pow(attempts.max(1) as u32 - 1);
+    RETRY_DELAY
+        .checked_mul(factor)
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+}
+
+/// Queues delivery again after `delay`.
+fn retry_later(delivery: Delivery, delay: Duration, retry_tx: UnboundedSender<Delivery>) {
+    tokio::spawn(async move {
+        time::sleep(delay).await;
+        retry_tx.send(delivery).ok();
+    });
+}
+
+/// Queues message for each recipient it's stored in the outbox for. Storing is retried
+/// while errors are transient, meanwhile no other messages are stored, so the progress never
+/// advances past a message which wasn't stored. A message which can never be stored
+/// is skipped, so it doesn't stop delivery of the following ones.
+async fn enqueue(conn: &mut ConnectionManager, msg: Message, queue: &mut Queue) {
+    let result = store_with_retry(|| {
+        let (mut conn, msg) = (conn.clone(), &msg);
+        async move { store(&mut conn, msg).await }
+    })
+    .await;
+
+    match result {
+        Ok(deliveries) => {
+            for delivery in deliveries {
+                queue.push(delivery);
+            }
+        }
+        Err(err) => {
+            error!("Skipping message which can't be stored: {}", err);
+            debug!("{:?}", msg);
+            if let Err(err) = skip(&msg).await {
+                error!("{}", err);
+            }
+        }
+    }
+}
+
+/// Calls `store` until it succeeds or fails with an error which isn't transient.
+async fn store_with_retry<T, F, Fut>(mut store: F) -> Result<T, StoreError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, StoreError>>,
+{
+    let mut attempts = 0;
+    loop {
+        match store().await {
+            Ok(stored) => return Ok(stored),
+            Err(err) if err.is_transient() => {
+                attempts += 1;
+                let delay = retry_delay(attempts).min(MAX_STORE_DELAY);
+                error!("Failed to store message, retrying in {:?}: {}", delay, err);
+                time::sleep(delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Records message as handled for all recipients without storing it, and removes rows
+/// it claims, so it's neither retried nor sent when the transaction is replayed.
+async fn skip(msg: &Message) -> Result<(), sqlx::Error> {
+    let pool = pg_pool().await;
+    let mut tx = pool.begin().await?;
+
+    if let Some(claim) = &msg.claim {
+        db::claim(&mut tx, claim).await?;
+    }
+    if let Some(index) = msg.index {
+        for &user_id in &msg.user_ids {
+            db::mark_handled(&mut tx, index, msg.seq, user_id).await?;
+        }
+        db::set_latest_index(&mut tx, index).await?;
+    }
+
+    tx.commit().await
+}
+
+/// Stores message in the outbox for each recipient, unless the recipient receives digests
+/// or quiet hours defer the message. Recipients who were handled before, when the transaction
+/// is replayed, are skipped. The progress is advanced and rows claimed by the message
+/// are removed in the same database transaction.
+async fn store(conn: &mut ConnectionManager, msg: &Message) -> Result<Vec<Delivery>, StoreError> {
+    let pool = pg_pool().await;
+    let mut tx = pool.begin().await?;
+    let mut deliveries = Vec::new();
+
+    if let Some(claim) = &msg.claim {
+        if !db::claim(&mut tx, claim).await? {
+            debug!("{:?} was already stored", claim);
+            return Ok(deliveries);
+        }
+    }
+
+    for user_id in recipients(&mut tx, msg).await? {
+        if let Some(event) = &msg.digest_event {
+            let mode = db::delivery_mode(conn, user_id).await?;
+            if mode != DeliveryMode::Immediate {
+                db::add_digest_event(&mut tx, user_id, event).await?;
+                debug!("Event added to digest of Telegram ID {}", user_id);
+                continue;
+            }
+        }
+
+        let deferred = match db::quiet_hours(conn, user_id).await? {
+            Some(quiet_hours) => {
+                quiet_hours.mode == QuietMode::Defer
+                    && quiet_hours.is_active(Utc::now())
+                    && !msg.is_critical()
+            }
+            None => false,
+        };
+
+        if deferred {
+            db::defer_message(&mut tx, user_id, &msg.text, msg.reply_markup.as_ref()).await?;
+            debug!("Message deferred for Telegram ID {}", user_id);
+            continue;
+        }
+
+        // Whether the message is silent is decided when it's sent, which may be much later
+        let text = label_addresses(conn, user_id, &msg.text).await?;
+        let critical = msg.is_critical();
+        let id =
+            db::add_to_outbox(&mut tx, user_id, &text, msg.reply_markup.as_ref(), critical).await?;
+        deliveries.push(Delivery {
+            id,
+            chat_id: user_id,
+            text,
+            reply_markup: msg.reply_markup.clone(),
+            critical,
+            attempts: 0,
+        });
+    }
+
+    if let Some(index) = msg.index {
+        db::set_latest_index(&mut tx, index).await?;
+    }
+
+    tx.commit().await?;
+    Ok(deliveries)
+}
+
+/// Returns recipients of the message, except those it was already handled for
+/// when the transaction is replayed, and records it as handled for the rest.
+async fn recipients(conn: &mut PgConnection, msg: &Message) -> Result<Vec<i64>, sqlx::Error> {
+    let mut user_ids = Vec::new();
+    for &user_id in &msg.user_ids {
+        if let Some(index) = msg.index {
+            if !db::mark_handled(conn, index, msg.seq, user_id).await? {
+                debug!(
+                    "{:?} was already handled for Telegram ID {}",
+                    index, user_id
+                );
+                continue;
+            }
+        }
+        user_ids.push(user_id);
+    }
+    Ok(user_ids)
+}
+
+/// Formats addresses in text with labels and address book names of the Telegram user.
+async fn label_addresses(
+    conn: &mut ConnectionManager,
+    user_id: i64,
+    text: &str,
+) -> Result<String, RedisError> {
+    let labels = db::labels(conn, user_id, &tagged_addresses(text)).await?;
+    Ok(format_tagged_addresses(text, &labels))
+}
+
+/// Returns `true` if quiet hours of the Telegram user are active now.
+async fn is_quiet(chat_id: i64) -> bool {
+    let mut cm = redis_cm().await.clone();
+    match db::quiet_hours(&mut cm, chat_id).await {
+        Ok(quiet_hours) => quiet_hours.is_some_and(|quiet_hours| quiet_hours.is_active(Utc::now())),
+        Err(err) => {
+            error!("{}", err);
+            false
+        }
+    }
+}
+
+/// Sends message in a spawned task, the result is reported to `sent_tx`.
+fn send(bot: &BotType, delivery: Delivery, sent_tx: UnboundedSender<Sent>) {
+    let bot = bot.clone();
+    tokio::spawn(async move {
+        let mut request = bot.send_message(delivery.chat_id, delivery.text.clone());
+        if let Some(reply_markup) = &delivery.reply_markup {
+            request = request.reply_markup(reply_markup.clone());
+        }
+        if !delivery.critical && is_quiet(delivery.chat_id).await {
+            request = request.disable_notification(true);
+        }
+        let result = request.await.map(|_| ());
+        sent_tx.send(Sent { delivery, result }).ok();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{Connection, Executor};
+    use std::cell::RefCell;
+    use std::io;
+
+    /// Connects to a scratch database given by `TEST_POSTGRESQL_URL`,
+    /// tests needing one are skipped if it's not set.
+    async fn test_connection() -> Option<PgConnection> {
+        let url = std::env::var("TEST_POSTGRESQL_URL").ok()?;
+        Some(PgConnection::connect(&url).await.unwrap())
+    }
+
+    /// Stores text like the outbox does, PostgreSQL rejects text containing NUL.
+    async fn store_text(stored: &RefCell<Vec<String>>, text: &str) -> Result<(), StoreError> {
+        if text.contains('\0') {
+            let err = "invalid byte sequence for encoding \"UTF8\": 0x00";
+            return Err(sqlx::Error::Protocol(err.into()).into());
+        }
+        stored.borrow_mut().push(text.to_owned());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unstorable_message_does_not_block_the_next_one() {
+        let stored = RefCell::new(Vec::new());
+
+        let unstorable = store_with_retry(|| store_text(&stored, "a\0"));
+        let result = time::timeout(Duration::from_secs(1), unstorable).await;
+        assert!(matches!(result, Ok(Err(_))), "{:?}", result);
+
+        store_with_retry(|| store_text(&stored, "b")).await.unwrap();
+        assert_eq!(stored.into_inner(), vec!["b"]);
+    }
+
+    #[test]
+    fn only_errors_which_may_pass_are_retried() {
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        assert!(StoreError::from(sqlx::Error::Io(reset)).is_transient());
+        assert!(StoreError::from(sqlx::Error::PoolTimedOut).is_transient());
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert!(StoreError::from(RedisError::from(refused)).is_transient());
+
+        assert!(!StoreError::from(sqlx::Error::Protocol("invalid message".into())).is_transient());
+        assert!(!StoreError::from(sqlx::Error::RowNotFound).is_transient());
+        let type_error = RedisError::from((redis::ErrorKind::TypeError, "not a hash"));
+        assert!(!StoreError::from(type_error).is_transient());
+    }
+
+    #[tokio::test]
+    async fn replayed_transaction_skips_handled_recipients() {
+        let mut conn = match test_connection().await {
+            Some(conn) => conn,
+            None => return,
+        };
+        // Tables are created in a schema of the transaction, which is rolled back
+        let mut tx = conn.begin().await.unwrap();
+        tx.execute("CREATE SCHEMA replay_test; SET LOCAL search_path TO replay_test")
+            .await
+            .unwrap();
+        tx.execute(include_str!("../migrations/20220524120000_progress.sql"))
+            .await
+            .unwrap();
+
+        let index = TransactionIndex::Account(5);
+        let msg = Message::new(index, 0, vec![1, 2], "a".into());
+        assert_eq!(recipients(&mut tx, &msg).await.unwrap(), vec![1, 2]);
+        db::set_latest_index(&mut tx, index).await.unwrap();
+
+        // Catching up replays the latest handled transaction, a new subscriber gets the message
+        let replayed = Message::new(index, 0, vec![1, 2, 3], "a".into());
+        assert_eq!(recipients(&mut tx, &replayed).await.unwrap(), vec![3]);
+        let next = Message::new(index, 1, vec![1, 2], "b".into());
+        assert_eq!(recipients(&mut tx, &next).await.unwrap(), vec![1, 2]);
+
+        let later = TransactionIndex::Account(6);
+        db::set_latest_index(&mut tx, later).await.unwrap();
+        db::set_latest_index(&mut tx, index).await.unwrap();
+        let (latest_id,): (i64,) = sqlx::query_as("SELECT latest_id FROM progress")
+            .fetch_one(&mut tx)
+            .await
+            .unwrap();
+        assert_eq!(latest_id, 6);
+
+        tx.rollback().await.unwrap();
     }
 }
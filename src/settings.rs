@@ -0,0 +1,72 @@
+//! Inline keyboard for enabling and disabling categories of events per subscription.
+use crate::types::{Category, Subscriber};
+use crate::{db, BotType};
+use log::*;
+use teloxide::payloads::{AnswerCallbackQuerySetters, EditMessageReplyMarkupSetters};
+use teloxide::types::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup};
+use teloxide::{prelude::*, requests::ResponseResult};
+
+/// Prefix of callback data of category buttons, followed by `<subscription ID>:<category>`.
+const CATEGORY_PREFIX: &str = "category:";
+
+/// Builds keyboard with a toggle button for each category of events of subscription with ID.
+pub fn keyboard(id: i32, subscriber: &Subscriber) -> InlineKeyboardMarkup {
+    let categories = Category::variants();
+    let buttons = categories.iter().map(|category| {
+        let mark = if subscriber.accepts(*category) {
+            "✅"
+        } else {
+            "❌"
+        };
+        let data = format!("{}{}:{}", CATEGORY_PREFIX, id, category.key());
+        vec![InlineKeyboardButton::callback(
+            format!("{} {}", mark, category),
+            data,
+        )]
+    });
+
+    InlineKeyboardMarkup::new(buttons)
+}
+
+/// Toggles category of events for a subscription when its button is pressed.
+pub async fn handle_callback_query(cx: UpdateWithCx<BotType, CallbackQuery>) -> ResponseResult<()> {
+    let query = &cx.update;
+    let (id, category) = match query.data.as_deref().and_then(parse_category_data) {
+        Some(data) => data,
+        None => {
+            cx.requester.answer_callback_query(query.id.clone()).await?;
+            return Ok(());
+        }
+    };
+
+    let answer = cx.requester.answer_callback_query(query.id.clone());
+    match db::toggle_category(query.from.id, id, category).await {
+        Ok(Some((_, subscriber))) => {
+            if let Some(message) = &query.message {
+                cx.requester
+                    .edit_message_reply_markup(message.chat.id, message.id)
+                    .reply_markup(keyboard(id, &subscriber))
+                    .await?;
+            }
+            answer.await?;
+        }
+        Ok(None) => {
+            answer
+                .text("You're not subscribed for this address")
+                .await?;
+        }
+        Err(err) => {
+            error!("{}", err);
+            answer
+                .text("A database query error has occurred 😐")
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_category_data(data: &str) -> Option<(i32, Category)> {
+    let (id, category) = data.strip_prefix(CATEGORY_PREFIX)?.split_once(':')?;
+    Some((id.parse().ok()?, category.parse().ok()?))
+}
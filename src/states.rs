@@ -1,3 +1,4 @@
+use crate::types::AccountAddress;
 use derive_more::From;
 use serde::{Deserialize, Serialize};
 use std::cmp::PartialEq;
@@ -7,6 +8,13 @@ use teloxide::dispatching::dialogue::Transition;
 pub enum Dialogue {
     Start(StartState),
     ReceiveAddress(ReceiveAddressState),
+    ReceiveDirection(ReceiveDirectionState),
+    ReceiveThreshold(ReceiveThresholdState),
+    ReceiveLabel(ReceiveLabelState),
+    ReceiveName(ReceiveNameState),
+    ReceiveDeliveryMode(ReceiveDeliveryModeState),
+    ReceiveDigestTime(ReceiveDigestTimeState),
+    ReceiveQuietHours(ReceiveQuietHoursState),
 }
 
 impl Default for Dialogue {
@@ -23,4 +31,41 @@ pub enum ReceiveAddressState {
     Balance,
     Subscribe,
     Unsubscribe,
+    Direction,
+    Threshold,
+    Settings,
+    Rename,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct ReceiveDirectionState {
+    pub address: AccountAddress,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReceiveThresholdState {
+    pub address: AccountAddress,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReceiveLabelState {
+    pub address: AccountAddress,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReceiveNameState {
+    /// Account address or contract address formatted as `<index,subindex>`.
+    pub address: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReceiveDeliveryModeState;
+
+/// Receives local time of daily digests followed by offset from UTC, e.g. `09:00 +02:00`.
+#[derive(Serialize, Deserialize)]
+pub struct ReceiveDigestTimeState;
+
+/// Receives quiet hours followed by offset from UTC and optional quiet mode,
+/// e.g. `23:00-08:00 +02:00 defer`.
+#[derive(Serialize, Deserialize)]
+pub struct ReceiveQuietHoursState;
@@ -1,19 +1,41 @@
 use super::states::*;
-use crate::types::{AccountAddress, Amount};
-use crate::{command::Command, db, rpc, states::Dialogue, BotType};
+use crate::types::{
+    self, AccountAddress, Amount, ContractAddress, DeliveryMode, QuietHours, QuietMode,
+};
+use crate::{
+    address_book, command::Command, db, redis_cm, rpc, settings, states::Dialogue, BotType,
+};
+use chrono::{FixedOffset, NaiveTime};
 use log::*;
 use teloxide::payloads::SendMessageSetters;
 use teloxide::types::{KeyboardButton, KeyboardMarkup, KeyboardRemove};
-use teloxide::utils::command::BotCommand;
+use teloxide::utils::{command::BotCommand, html};
 use teloxide::{prelude::*, requests::ResponseResult};
 
 const BOT_NAME: &str = "Congruity";
+const MAX_LABEL_LEN: usize = 32;
 
-async fn subscribe(address: &AccountAddress, cx: TransitionIn<BotType>) -> ResponseResult<Message> {
+async fn subscribe(
+    address: &AccountAddress,
+    label: Option<&str>,
+    cx: TransitionIn<BotType>,
+) -> ResponseResult<Message> {
     let user_id = cx.chat_id() as i64;
-    let result = db::subscribe(user_id, address).await;
+    let result = db::subscribe(user_id, address, None, label).await;
     match result {
-        Ok(true) => cx.answer("Subscribed successfully").await,
+        Ok(true) => {
+            // Index of the account identifies the baker pool it may own
+            match rpc::get_account_index(address.address()).await {
+                Ok(Some(index)) => {
+                    if let Err(err) = db::set_account_index(address, index).await {
+                        error!("Storing index of account {} failed: {}", address, err);
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => error!("Looking up index of account {} failed: {}", address, err),
+            }
+            cx.answer("Subscribed successfully").await
+        }
         Ok(false) => {
             cx.answer("You're already subscribed for this address")
                 .await
@@ -22,6 +44,21 @@ async fn subscribe(address: &AccountAddress, cx: TransitionIn<BotType>) -> Respo
     }
 }
 
+async fn subscribe_contract(
+    contract: &ContractAddress,
+    cx: TransitionIn<BotType>,
+) -> ResponseResult<Message> {
+    let user_id = cx.chat_id();
+    match db::subscribe_contract(user_id, contract).await {
+        Ok(true) => cx.answer("Subscribed successfully").await,
+        Ok(false) => {
+            cx.answer("You're already subscribed for this contract")
+                .await
+        }
+        Err(err) => cx.answer(format!("{}", err)).await,
+    }
+}
+
 async fn answer_after_keyboard(cx: TransitionIn<BotType>, text: &str) -> ResponseResult<Message> {
     cx.requester
         .send_message(cx.chat_id(), text)
@@ -54,6 +91,201 @@ async fn unsubscribe(
     }
 }
 
+async fn unsubscribe_contract(
+    contract: &ContractAddress,
+    cx: TransitionIn<BotType>,
+) -> ResponseResult<Message> {
+    match db::unsubscribe_contract(cx.chat_id(), contract).await {
+        Ok(true) => answer_after_keyboard(cx, "Unsubscribed successfully").await,
+        Ok(false) => answer_after_keyboard(cx, "You're not subscribed for this contract").await,
+        Err(err) => {
+            error!("{}", err);
+            answer_after_keyboard(cx, "A database query error has occurred 😐").await
+        }
+    }
+}
+
+async fn set_direction(
+    address: &AccountAddress,
+    direction: types::Direction,
+    cx: TransitionIn<BotType>,
+) -> ResponseResult<Message> {
+    match db::set_direction(cx.chat_id(), address, direction).await {
+        Ok(true) => {
+            let answer = match direction {
+                types::Direction::Both => {
+                    "OK, notifying about incoming and outgoing transfers".to_string()
+                }
+                direction => format!("OK, notifying about {} transfers only", direction),
+            };
+            answer_after_keyboard(cx, &answer).await
+        }
+        Ok(false) => answer_after_keyboard(cx, "You're not subscribed for this address").await,
+        Err(err) => {
+            error!("{}", err);
+            answer_after_keyboard(cx, "A database query error has occurred 😐").await
+        }
+    }
+}
+
+async fn set_threshold(
+    address: &AccountAddress,
+    threshold: Option<Amount>,
+    cx: TransitionIn<BotType>,
+) -> ResponseResult<Message> {
+    match db::set_threshold(cx.chat_id(), address, threshold).await {
+        Ok(true) => {
+            let answer = match threshold {
+                Some(threshold) => {
+                    format!("OK, notifying about transfers of {} CCD or more", threshold)
+                }
+                None => "OK, notifying about transfers of any amount".to_string(),
+            };
+            cx.answer(answer).await
+        }
+        Ok(false) => cx.answer("You're not subscribed for this address").await,
+        Err(err) => {
+            error!("{}", err);
+            cx.answer("A database query error has occurred 😐").await
+        }
+    }
+}
+
+async fn set_label(
+    address: &AccountAddress,
+    label: Option<&str>,
+    cx: TransitionIn<BotType>,
+) -> ResponseResult<Message> {
+    match db::set_label(cx.chat_id(), address, label).await {
+        Ok(true) => match label {
+            Some(label) => {
+                cx.answer(format!(
+                    "OK, the account is now labelled {}",
+                    html::escape(label)
+                ))
+                .await
+            }
+            None => cx.answer("OK, the label is removed").await,
+        },
+        Ok(false) => cx.answer("You're not subscribed for this address").await,
+        Err(err) => {
+            error!("{}", err);
+            cx.answer("A database query error has occurred 😐").await
+        }
+    }
+}
+
+/// Returns `true` if label is too long to be shown in notifications.
+fn is_label_too_long(label: &str) -> bool {
+    label.chars().count() > MAX_LABEL_LEN
+}
+
+/// Returns address of a subscription from the list, which may be formatted as `label: address`.
+fn subscription_address(subscription: &str) -> &str {
+    subscription.rsplit(' ').next().unwrap_or(subscription)
+}
+
+/// Shows buttons for enabling and disabling categories of events for a subscribed account.
+async fn show_settings(
+    address: &AccountAddress,
+    cx: TransitionIn<BotType>,
+) -> ResponseResult<Message> {
+    match db::subscription_settings(cx.chat_id(), address).await {
+        Ok(Some((id, subscriber))) => {
+            cx.requester
+                .send_message(cx.chat_id(), "OK, choose events to be notified about")
+                .reply_markup(settings::keyboard(id, &subscriber))
+                .await
+        }
+        Ok(None) => answer_after_keyboard(cx, "You're not subscribed for this address").await,
+        Err(err) => {
+            error!("{}", err);
+            answer_after_keyboard(cx, "A database query error has occurred 😐").await
+        }
+    }
+}
+
+async fn set_delivery_mode(
+    mode: DeliveryMode,
+    cx: TransitionIn<BotType>,
+) -> ResponseResult<Message> {
+    match db::set_delivery_mode(cx.chat_id(), mode).await {
+        Ok(()) => {
+            let answer = match mode {
+                DeliveryMode::Immediate => "OK, notifying about events immediately",
+                DeliveryMode::Hourly => "OK, sending a digest of events every hour",
+                DeliveryMode::Daily => "OK, sending a digest of events every day",
+            };
+            answer_after_keyboard(cx, answer).await
+        }
+        Err(err) => {
+            error!("{}", err);
+            answer_after_keyboard(cx, "A database query error has occurred 😐").await
+        }
+    }
+}
+
+/// Parses local time of daily digests followed by offset from UTC, e.g. `09:00 +02:00`.
+fn parse_digest_time(text: &str) -> Option<(NaiveTime, FixedOffset)> {
+    let (time, offset) = text.trim().split_once(char::is_whitespace)?;
+    Some((
+        types::parse_time_of_day(time)?,
+        types::parse_utc_offset(offset.trim())?,
+    ))
+}
+
+/// Parses quiet hours followed by offset from UTC and optional quiet mode,
+/// e.g. `23:00-08:00 +02:00 defer`. Notifications are silent by default.
+fn parse_quiet_hours(text: &str) -> Option<QuietHours> {
+    let mut parts = text.split_whitespace();
+    let (start, end) = parts.next()?.split_once('-')?;
+    let offset = types::parse_utc_offset(parts.next()?)?;
+    let mode = match parts.next() {
+        Some(mode) => mode.parse().ok()?,
+        None => QuietMode::Silent,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(QuietHours::new(
+        types::parse_time_of_day(start)?,
+        types::parse_time_of_day(end)?,
+        offset,
+        mode,
+    ))
+}
+
+/// Asks the user to choose one of the subscribed accounts for the next dialogue state.
+async fn choose_account(
+    cx: TransitionIn<BotType>,
+    state: ReceiveAddressState,
+) -> TransitionOut<Dialogue> {
+    let user_id = cx.chat_id();
+    let subscriptions: Vec<String> = db::subscriptions(user_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|subscription| {
+            subscription_address(subscription)
+                .parse::<AccountAddress>()
+                .is_ok()
+        })
+        .collect();
+
+    if !subscriptions.is_empty() {
+        let keyboard = build_keyboard(&subscriptions);
+        cx.requester
+            .send_message(user_id, "OK, send me address of the account")
+            .reply_markup(keyboard)
+            .await?;
+        next(state)
+    } else {
+        cx.answer("No subscriptions were found").await?;
+        next(StartState)
+    }
+}
+
 async fn get_account_balance(
     addr: &AccountAddress,
     cx: TransitionIn<BotType>,
@@ -72,6 +304,30 @@ async fn get_account_balance(
     }
 }
 
+/// Splits text into an address and a label following it.
+/// Contract addresses like `<1, 0>` may contain whitespace, so they are split off first.
+fn split_label(text: &str) -> (&str, Option<&str>) {
+    if text.parse::<ContractAddress>().is_ok() {
+        return (text, None);
+    }
+
+    let contract_end = text
+        .find('>')
+        .filter(|_| text.starts_with('<'))
+        .map(|end| end + 1);
+    let (address, label) = match contract_end {
+        Some(end) => text.split_at(end),
+        None => text.split_once(char::is_whitespace).unwrap_or((text, "")),
+    };
+
+    let label = label.trim();
+    if label.is_empty() {
+        (address, None)
+    } else {
+        (address, Some(label))
+    }
+}
+
 fn build_keyboard(buttons: &[String]) -> KeyboardMarkup {
     let buttons = buttons.iter().map(|text| {
         [KeyboardButton {
@@ -109,7 +365,11 @@ async fn start(
                 return next(ReceiveAddressState::Balance);
             }
             Command::Subscribe => {
-                cx.answer("OK, send me address of the account").await?;
+                cx.answer(
+                    "OK, send me address of the account or contract as &lt;index,subindex&gt;\n\
+                     An account address can be followed by a label, e.g. <code>address Savings</code>",
+                )
+                .await?;
                 return next(ReceiveAddressState::Subscribe);
             }
             Command::Subscriptions => {
@@ -117,7 +377,7 @@ async fn start(
                 let subscriptions = db::subscriptions(user_id).await.unwrap();
 
                 if subscriptions.len() > 0 {
-                    cx.answer(subscriptions.join("\n")).await?;
+                    cx.answer(html::escape(&subscriptions.join("\n"))).await?;
                 } else {
                     cx.answer("No subscriptions were found").await?;
                 }
@@ -140,6 +400,86 @@ async fn start(
                     cx.answer("No subscriptions were found").await?;
                 }
             }
+            Command::Direction => {
+                return choose_account(cx, ReceiveAddressState::Direction).await;
+            }
+            Command::Threshold => {
+                return choose_account(cx, ReceiveAddressState::Threshold).await;
+            }
+            Command::Settings => {
+                return choose_account(cx, ReceiveAddressState::Settings).await;
+            }
+            Command::Rename => {
+                return choose_account(cx, ReceiveAddressState::Rename).await;
+            }
+            Command::AddressBook => {
+                let entries = db::address_book(cx.chat_id()).await.unwrap();
+
+                if !entries.is_empty() {
+                    cx.answer(html::escape(&entries.join("\n"))).await?;
+                } else {
+                    cx.answer("The address book is empty, save senders from notifications")
+                        .await?;
+                }
+            }
+            Command::Digest => {
+                let answer = match db::digest_settings(cx.chat_id()).await {
+                    Ok(Some(settings)) if settings.delivery_mode == DeliveryMode::Daily => format!(
+                        "Digests are sent daily at {} UTC{}\nOK, choose delivery mode",
+                        settings.digest_time.format("%H:%M"),
+                        settings.utc_offset
+                    ),
+                    Ok(Some(settings)) => format!(
+                        "Delivery mode is {}\nOK, choose delivery mode",
+                        settings.delivery_mode
+                    ),
+                    Ok(None) => "Delivery mode is immediate\nOK, choose delivery mode".to_string(),
+                    Err(err) => {
+                        error!("{}", err);
+                        cx.answer("A database query error has occurred 😐").await?;
+                        return next(state);
+                    }
+                };
+                let modes: Vec<String> = DeliveryMode::variants()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                cx.requester
+                    .send_message(cx.chat_id(), answer)
+                    .reply_markup(build_keyboard(&modes))
+                    .await?;
+                return next(ReceiveDeliveryModeState);
+            }
+            Command::Quiet => {
+                let user_id = cx.chat_id();
+                let mut redis = redis_cm().await.clone();
+                let answer = match db::quiet_hours(&mut redis, user_id).await.unwrap() {
+                    Some(quiet_hours) => format!("Quiet hours are {}\n", quiet_hours),
+                    None => "Quiet hours are disabled\n".to_string(),
+                };
+                cx.answer(format!(
+                    "{}OK, send me quiet hours and your offset from UTC, e.g. <code>23:00-08:00 +02:00</code>\n\
+                     Notifications are sent silently, add <code>defer</code> to hold them until quiet hours end. \
+                     Rejected transactions and credential changes are always notified about. \
+                     Send - to disable quiet hours",
+                    answer
+                ))
+                .await?;
+                return next(ReceiveQuietHoursState);
+            }
+            Command::ChainUpdates => match db::toggle_chain_updates(cx.chat_id()).await {
+                Ok(true) => {
+                    cx.answer("Subscribed to chain parameter updates").await?;
+                }
+                Ok(false) => {
+                    cx.answer("Unsubscribed from chain parameter updates")
+                        .await?;
+                }
+                Err(err) => {
+                    error!("{}", err);
+                    cx.answer("A database query error has occurred 😐").await?;
+                }
+            },
         }
     } else {
         cx.answer("Don't understand 🤷‍♂️").await?;
@@ -152,30 +492,279 @@ async fn start(
 async fn recieve_balance(
     state: ReceiveAddressState,
     cx: TransitionIn<BotType>,
-    address: String,
+    text: String,
 ) -> TransitionOut<Dialogue> {
     use ReceiveAddressState::*;
 
+    // An address to subscribe can be followed by a label,
+    // addresses chosen from the keyboard may be prefixed with their labels
+    let text = text.trim();
+    let (address, label) = if state == Subscribe {
+        split_label(text)
+    } else {
+        (subscription_address(text), None)
+    };
+
     if state == Unsubscribe && address == "all" {
         unsubscribe_all(cx).await?;
         return next(StartState);
     }
 
+    if state == Subscribe || state == Unsubscribe {
+        if let Ok(contract) = address.parse::<ContractAddress>() {
+            debug!("{:?} {}", state, contract);
+            if state == Subscribe {
+                subscribe_contract(&contract, cx).await?;
+            } else {
+                unsubscribe_contract(&contract, cx).await?;
+            }
+            return next(StartState);
+        }
+    }
+
     if let Ok(address) = address.parse::<AccountAddress>() {
         debug!("{:?} {}", state, address);
         match state {
             Balance => {
                 get_account_balance(&address, cx).await?;
             }
-            Subscribe => {
-                subscribe(&address, cx).await?;
-            }
+            Subscribe => match label {
+                Some(label) if is_label_too_long(label) => {
+                    let answer = format!("Label can't be longer than {} characters", MAX_LABEL_LEN);
+                    cx.answer(answer).await?;
+                }
+                label => {
+                    subscribe(&address, label, cx).await?;
+                }
+            },
             Unsubscribe => {
                 unsubscribe(&address, cx).await?;
             }
+            Direction => {
+                let directions: Vec<String> = types::Direction::variants()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                cx.requester
+                    .send_message(cx.chat_id(), "OK, choose direction of transfers")
+                    .reply_markup(build_keyboard(&directions))
+                    .await?;
+                return next(ReceiveDirectionState { address });
+            }
+            Threshold => {
+                answer_after_keyboard(
+                    cx,
+                    "OK, send me minimum amount of transfers in CCD, or 0 to be notified about any amount",
+                )
+                .await?;
+                return next(ReceiveThresholdState { address });
+            }
+            Settings => {
+                show_settings(&address, cx).await?;
+            }
+            Rename => {
+                answer_after_keyboard(
+                    cx,
+                    "OK, send me a new label for the account, or - to remove it",
+                )
+                .await?;
+                return next(ReceiveLabelState { address });
+            }
         };
     } else {
         cx.answer("Invalid account address").await?;
     }
     next(StartState)
 }
+
+#[teloxide(subtransition)]
+async fn receive_direction(
+    state: ReceiveDirectionState,
+    cx: TransitionIn<BotType>,
+    direction: String,
+) -> TransitionOut<Dialogue> {
+    match direction.parse() {
+        Ok(direction) => {
+            set_direction(&state.address, direction, cx).await?;
+        }
+        Err(err) => {
+            answer_after_keyboard(cx, &err.to_string()).await?;
+        }
+    }
+    next(StartState)
+}
+
+#[teloxide(subtransition)]
+async fn receive_threshold(
+    state: ReceiveThresholdState,
+    cx: TransitionIn<BotType>,
+    threshold: String,
+) -> TransitionOut<Dialogue> {
+    match Amount::from_ccd(threshold.trim()) {
+        Some(threshold) if threshold == Amount::from(0) => {
+            set_threshold(&state.address, None, cx).await?;
+        }
+        Some(threshold) => {
+            set_threshold(&state.address, Some(threshold), cx).await?;
+        }
+        None => {
+            cx.answer("Invalid amount").await?;
+        }
+    }
+    next(StartState)
+}
+
+#[teloxide(subtransition)]
+async fn receive_label(
+    state: ReceiveLabelState,
+    cx: TransitionIn<BotType>,
+    label: String,
+) -> TransitionOut<Dialogue> {
+    match label.trim() {
+        "-" => {
+            set_label(&state.address, None, cx).await?;
+        }
+        "" => {
+            cx.answer("Label can't be empty").await?;
+        }
+        label if is_label_too_long(label) => {
+            let answer = format!("Label can't be longer than {} characters", MAX_LABEL_LEN);
+            cx.answer(answer).await?;
+        }
+        label => {
+            set_label(&state.address, Some(label), cx).await?;
+        }
+    }
+    next(StartState)
+}
+
+#[teloxide(subtransition)]
+async fn receive_name(
+    state: ReceiveNameState,
+    cx: TransitionIn<BotType>,
+    name: String,
+) -> TransitionOut<Dialogue> {
+    if !address_book::is_address(&state.address) {
+        cx.answer("Invalid address").await?;
+        return next(StartState);
+    }
+
+    let name = match name.trim() {
+        "-" => None,
+        "" => {
+            cx.answer("Name can't be empty").await?;
+            return next(StartState);
+        }
+        name if is_label_too_long(name) => {
+            let answer = format!("Name can't be longer than {} characters", MAX_LABEL_LEN);
+            cx.answer(answer).await?;
+            return next(StartState);
+        }
+        name => Some(name),
+    };
+
+    match db::save_address(cx.chat_id(), &state.address, name).await {
+        Ok(_) if name.is_some() => {
+            cx.answer("OK, the address is saved to the address book")
+                .await?;
+        }
+        Ok(true) => {
+            cx.answer("OK, the address is removed from the address book")
+                .await?;
+        }
+        Ok(false) => {
+            cx.answer("The address is not in the address book").await?;
+        }
+        Err(err) => {
+            error!("{}", err);
+            cx.answer("A database query error has occurred 😐").await?;
+        }
+    }
+    next(StartState)
+}
+
+#[teloxide(subtransition)]
+async fn receive_delivery_mode(
+    _state: ReceiveDeliveryModeState,
+    cx: TransitionIn<BotType>,
+    mode: String,
+) -> TransitionOut<Dialogue> {
+    match mode.parse() {
+        Ok(DeliveryMode::Daily) => {
+            answer_after_keyboard(
+                cx,
+                "OK, send me time of the digest and your offset from UTC, e.g. <code>09:00 +02:00</code>",
+            )
+            .await?;
+            return next(ReceiveDigestTimeState);
+        }
+        Ok(mode) => {
+            set_delivery_mode(mode, cx).await?;
+        }
+        Err(err) => {
+            answer_after_keyboard(cx, &err.to_string()).await?;
+        }
+    }
+    next(StartState)
+}
+
+#[teloxide(subtransition)]
+async fn receive_digest_time(
+    _state: ReceiveDigestTimeState,
+    cx: TransitionIn<BotType>,
+    text: String,
+) -> TransitionOut<Dialogue> {
+    let (time, offset) = match parse_digest_time(&text) {
+        Some(digest_time) => digest_time,
+        None => {
+            cx.answer("Invalid time or UTC offset").await?;
+            return next(StartState);
+        }
+    };
+
+    match db::set_digest_time(cx.chat_id(), time, offset).await {
+        Ok(()) => {
+            set_delivery_mode(DeliveryMode::Daily, cx).await?;
+        }
+        Err(err) => {
+            error!("{}", err);
+            cx.answer("A database query error has occurred 😐").await?;
+        }
+    }
+    next(StartState)
+}
+
+#[teloxide(subtransition)]
+async fn receive_quiet_hours(
+    _state: ReceiveQuietHoursState,
+    cx: TransitionIn<BotType>,
+    text: String,
+) -> TransitionOut<Dialogue> {
+    let quiet_hours = match text.trim() {
+        "-" => None,
+        text => match parse_quiet_hours(text) {
+            Some(quiet_hours) => Some(quiet_hours),
+            None => {
+                cx.answer("Invalid quiet hours").await?;
+                return next(StartState);
+            }
+        },
+    };
+
+    match db::set_quiet_hours(cx.chat_id(), quiet_hours.as_ref()).await {
+        Ok(()) => match quiet_hours {
+            Some(quiet_hours) => {
+                cx.answer(format!("OK, quiet hours are {}", quiet_hours))
+                    .await?;
+            }
+            None => {
+                cx.answer("OK, quiet hours are disabled").await?;
+            }
+        },
+        Err(err) => {
+            error!("{}", err);
+            cx.answer("A database query error has occurred 😐").await?;
+        }
+    }
+    next(StartState)
+}
@@ -1,5 +1,5 @@
 use base58check::FromBase58Check;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
 
@@ -11,7 +11,7 @@ impl fmt::Display for ParseAccountAddressError {
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AccountAddress(String);
 
 impl fmt::Display for AccountAddress {
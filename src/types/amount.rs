@@ -1,11 +1,12 @@
 use serde::de::{self, Visitor};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::error::Error;
 use std::fmt;
 use std::marker::PhantomData;
+use std::ops::Add;
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Amount(u64);
 
 impl FromStr for Amount {
@@ -16,18 +17,56 @@ impl FromStr for Amount {
     }
 }
 
+impl Amount {
+    /// Returns amount in microCCD.
+    pub fn micro_ccd(&self) -> u64 {
+        self.0
+    }
+
+    /// Parses amount given in CCD with up to 6 decimals, e.g. "10.5".
+    pub fn from_ccd(s: &str) -> Option<Self> {
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if whole.is_empty() || fraction.len() > 6 || !is_digits(whole) || !is_digits(fraction) {
+            return None;
+        }
+
+        let whole: u64 = whole.parse().ok()?;
+        let fraction: u64 = format!("{:0<6}", fraction).parse().ok()?;
+        whole.checked_mul(1000000)?.checked_add(fraction).map(Self)
+    }
+}
+
 impl From<u64> for Amount {
     fn from(amount: u64) -> Self {
         Self(amount)
     }
 }
 
+impl Add for Amount {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
 impl fmt::Display for Amount {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:.6}", self.0 as f64 / 1000000.0)
     }
 }
 
+/// Serializes amount as a string of microCCD, the same way as it's deserialized.
+impl Serialize for Amount {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
 impl<'de> Deserialize<'de> for Amount {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
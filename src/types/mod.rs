@@ -1,17 +1,74 @@
 mod account_address;
 mod amount;
+mod reject_reason;
+mod subscription;
+mod tolerant;
+mod update_payload;
+mod user;
 
 pub use account_address::AccountAddress;
 pub use amount::Amount;
+pub use reject_reason::RejectReason;
+use serde::de::IgnoredAny;
 use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+pub use subscription::{Category, Direction, Subscriber};
+use tolerant::Unrecognized;
+pub use update_payload::UpdatePayload;
+pub use user::{
+    parse_time_of_day, parse_utc_offset, DeliveryMode, DigestEvent, DigestSettings, QuietHours,
+    QuietMode,
+};
 
 #[derive(Debug)]
 pub struct AccountUpdate {
     pub index_id: i64,
     pub account: AccountAddress,
+    /// Slot time of the block in milliseconds.
+    pub block_time: u64,
     pub summary: BlockSummary,
 }
 
+#[derive(Debug)]
+pub struct ContractUpdate {
+    pub index_id: i64,
+    pub contract: ContractAddress,
+    pub summary: BlockSummary,
+}
+
+/// ID of a row in account (`ati`) or contract (`cti`) transaction index,
+/// or ID of a chain update or a pool update in `summaries`.
+#[derive(Clone, Copy, Debug)]
+pub enum TransactionIndex {
+    Account(i64),
+    Contract(i64),
+    ChainUpdate(i64),
+    PoolUpdate(i64),
+}
+
+impl TransactionIndex {
+    pub fn id(&self) -> i64 {
+        match self {
+            TransactionIndex::Account(id)
+            | TransactionIndex::Contract(id)
+            | TransactionIndex::ChainUpdate(id)
+            | TransactionIndex::PoolUpdate(id) => *id,
+        }
+    }
+
+    /// Name of the source of updates the progress is stored for.
+    pub fn source(&self) -> &'static str {
+        match self {
+            TransactionIndex::Account(_) => "ati",
+            TransactionIndex::Contract(_) => "cti",
+            TransactionIndex::ChainUpdate(_) => "chain_updates",
+            TransactionIndex::PoolUpdate(_) => "pool_updates",
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub enum BlockSummary {
     #[serde(rename = "Left", rename_all = "camelCase")]
@@ -25,15 +82,19 @@ pub enum BlockSummary {
         index: u64,
     },
     #[serde(rename = "Right")]
-    SpecialTransactionOutcome(OutcomeKind),
+    SpecialTransactionOutcome(#[serde(deserialize_with = "tolerant::value")] OutcomeKind),
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type", content = "contents", rename_all = "camelCase")]
 pub enum TransactionSummaryType {
-    AccountTransaction(TransactionType),
-    CredentialDeploymentTransaction,
-    UpdateTransaction,
+    /// Transaction type is `None` if the transaction payload could not be deserialized.
+    AccountTransaction(#[serde(deserialize_with = "tolerant::option")] Option<TransactionType>),
+    /// Credential type, `initial` or `normal`.
+    CredentialDeploymentTransaction(String),
+    /// Update type, e.g. `updateProtocol`, is ignored since the update is described
+    /// by the `UpdateEnqueued` event.
+    UpdateTransaction(IgnoredAny),
 }
 
 #[derive(Deserialize, Debug)]
@@ -58,13 +119,50 @@ pub enum TransactionType {
     TransferWithMemo,
     EncryptedAmountTransferWithMemo,
     TransferWithScheduleAndMemo,
+    ConfigureBaker,
+    ConfigureDelegation,
+    /// Transaction type introduced after this bot was written.
+    #[serde(skip_deserializing)]
+    Unknown(String),
+}
+
+impl Unrecognized for TransactionType {
+    fn unrecognized(value: Value) -> Self {
+        TransactionType::Unknown(value.as_str().unwrap_or_default().to_string())
+    }
+}
+
+impl fmt::Display for TransactionType {
+    /// Formats variant name as lowercase words, e.g. `TransferWithMemo` as "transfer with memo".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let TransactionType::Unknown(name) = self {
+            return write!(f, "{}", name);
+        }
+
+        let name = format!("{:?}", self);
+        let mut words = String::with_capacity(name.len() + 4);
+        for (i, c) in name.chars().enumerate() {
+            if c.is_uppercase() && i > 0 {
+                words.push(' ');
+            }
+            words.push(c.to_ascii_lowercase());
+        }
+        write!(f, "{}", words)
+    }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "outcome", rename_all = "camelCase")]
 pub enum TransactionOutcome {
-    Success { events: Vec<Event> },
-    Reject,
+    Success {
+        #[serde(deserialize_with = "tolerant::vec")]
+        events: Vec<Event>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Reject {
+        #[serde(deserialize_with = "tolerant::value")]
+        reject_reason: RejectReason,
+    },
 }
 
 /// Transaction execution events.
@@ -73,48 +171,241 @@ pub enum TransactionOutcome {
 #[serde(tag = "tag")]
 pub enum Event {
     ModuleDeployed,
-    ContractInitialized,
-    Updated,
+    #[serde(rename_all = "camelCase")]
+    ContractInitialized {
+        #[serde(rename = "ref")]
+        module_ref: String,
+        address: ContractAddress,
+        amount: Amount,
+        init_name: String,
+        events: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Updated {
+        address: ContractAddress,
+        instigator: Address,
+        amount: Amount,
+        receive_name: String,
+        events: Vec<String>,
+    },
     Transferred {
         from: Address,
         to: Address,
         amount: Amount,
     },
-    AccountCreated,
-    CredentialDeployed,
-    BakerAdded,
-    BakerRemoved,
-    BakerStakeIncreased,
-    BakerStakeDecreased,
-    BakerSetRestakeEarnings,
-    BakerKeysUpdated,
-    CredentialKeysUpdated,
-    NewEncryptedAmount,
+    AccountCreated {
+        contents: AccountAddress,
+    },
+    #[serde(rename_all = "camelCase")]
+    CredentialDeployed {
+        reg_id: String,
+        account: AccountAddress,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerAdded {
+        baker_id: u64,
+        account: AccountAddress,
+        stake: Amount,
+        restake_earnings: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerRemoved {
+        baker_id: u64,
+        account: AccountAddress,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerStakeIncreased {
+        baker_id: u64,
+        account: AccountAddress,
+        new_stake: Amount,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerStakeDecreased {
+        baker_id: u64,
+        account: AccountAddress,
+        new_stake: Amount,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerSetRestakeEarnings {
+        baker_id: u64,
+        account: AccountAddress,
+        restake_earnings: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerKeysUpdated {
+        baker_id: u64,
+        account: AccountAddress,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerSetOpenStatus {
+        baker_id: u64,
+        account: AccountAddress,
+        open_status: OpenStatus,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerSetMetadataURL {
+        baker_id: u64,
+        account: AccountAddress,
+        #[serde(rename = "metadataURL")]
+        metadata_url: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerSetTransactionFeeCommission {
+        baker_id: u64,
+        account: AccountAddress,
+        transaction_fee_commission: f64,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerSetBakingRewardCommission {
+        baker_id: u64,
+        account: AccountAddress,
+        baking_reward_commission: f64,
+    },
+    #[serde(rename_all = "camelCase")]
+    BakerSetFinalizationRewardCommission {
+        baker_id: u64,
+        account: AccountAddress,
+        finalization_reward_commission: f64,
+    },
+    #[serde(rename_all = "camelCase")]
+    DelegationAdded {
+        delegator_id: u64,
+        account: AccountAddress,
+    },
+    #[serde(rename_all = "camelCase")]
+    DelegationRemoved {
+        delegator_id: u64,
+        account: AccountAddress,
+    },
+    #[serde(rename_all = "camelCase")]
+    DelegationStakeIncreased {
+        delegator_id: u64,
+        account: AccountAddress,
+        new_stake: Amount,
+    },
+    #[serde(rename_all = "camelCase")]
+    DelegationStakeDecreased {
+        delegator_id: u64,
+        account: AccountAddress,
+        new_stake: Amount,
+    },
+    #[serde(rename_all = "camelCase")]
+    DelegationSetRestakeEarnings {
+        delegator_id: u64,
+        account: AccountAddress,
+        restake_earnings: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    DelegationSetDelegationTarget {
+        delegator_id: u64,
+        account: AccountAddress,
+        delegation_target: DelegationTarget,
+    },
+    #[serde(rename_all = "camelCase")]
+    CredentialKeysUpdated {
+        cred_id: String,
+    },
+    NewEncryptedAmount {
+        account: AccountAddress,
+    },
     EncryptedAmountsRemoved,
-    AmountAddedByDecryption,
-    EncryptedSelfAmountAdded,
-    UpdateEnqueued,
+    AmountAddedByDecryption {
+        account: AccountAddress,
+        amount: Amount,
+    },
+    EncryptedSelfAmountAdded {
+        account: AccountAddress,
+        amount: Amount,
+    },
+    #[serde(rename_all = "camelCase")]
+    UpdateEnqueued {
+        /// Unix time in seconds when the update takes effect.
+        effective_time: u64,
+        #[serde(deserialize_with = "tolerant::value")]
+        payload: UpdatePayload,
+    },
     TransferredWithSchedule {
         from: Address,
         to: Address,
         amount: AmountWithSchedule,
     },
-    CredentialsUpdated,
-    DataRegistered,
+    #[serde(rename_all = "camelCase")]
+    CredentialsUpdated {
+        account: AccountAddress,
+        new_cred_ids: Vec<String>,
+        removed_cred_ids: Vec<String>,
+        new_threshold: u8,
+    },
+    DataRegistered {
+        /// Hex-encoded data.
+        data: String,
+    },
     TransferMemo {
         memo: String,
     },
+    /// Event introduced after this bot was written.
+    #[serde(skip_deserializing)]
+    Unknown(Value),
+}
+
+impl Unrecognized for Event {
+    fn unrecognized(value: Value) -> Self {
+        Event::Unknown(value)
+    }
+}
+
+/// Whether a baker pool is open for delegation.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum OpenStatus {
+    OpenForAll,
+    ClosedForNew,
+    ClosedForAll,
+}
+
+impl fmt::Display for OpenStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match self {
+            OpenStatus::OpenForAll => "open for all",
+            OpenStatus::ClosedForNew => "closed for new delegators",
+            OpenStatus::ClosedForAll => "closed for all",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "delegateType")]
+pub enum DelegationTarget {
+    Passive,
+    #[serde(rename_all = "camelCase")]
+    Baker {
+        baker_id: u64,
+    },
+}
+
+impl fmt::Display for DelegationTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DelegationTarget::Passive => write!(f, "passive delegation"),
+            DelegationTarget::Baker { baker_id } => write!(f, "baker pool {}", baker_id),
+        }
+    }
 }
 
+/// Amounts released at timestamps given in milliseconds.
 #[derive(Deserialize, Debug)]
-pub struct AmountWithSchedule(Vec<(u64, String)>);
+pub struct AmountWithSchedule(Vec<(u64, Amount)>);
 
 impl AmountWithSchedule {
     pub fn total_amount(&self) -> Amount {
         self.0
             .iter()
-            .fold(0, |acc, (_, amount)| acc + amount.parse::<u64>().unwrap())
-            .into()
+            .fold(Amount::from(0), |acc, (_, amount)| acc + *amount)
+    }
+
+    pub fn releases(&self) -> &[(u64, Amount)] {
+        &self.0
     }
 }
 
@@ -123,12 +414,45 @@ pub struct TransferMemo {
     pub memo: String,
 }
 
-#[derive(Deserialize, Debug)]
+pub struct ParseContractAddressError;
+
+impl fmt::Display for ParseContractAddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid contract address")
+    }
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
 pub struct ContractAddress {
     pub index: u64,
     pub subindex: u64,
 }
 
+impl fmt::Display for ContractAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{},{}>", self.index, self.subindex)
+    }
+}
+
+impl FromStr for ContractAddress {
+    type Err = ParseContractAddressError;
+
+    /// Parses contract address formatted as `index,subindex` or `<index,subindex>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s
+            .strip_prefix('<')
+            .and_then(|s| s.strip_suffix('>'))
+            .unwrap_or(s);
+        let mut parts = s.split(',').map(|part| part.trim().parse::<u64>());
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(index)), Some(Ok(subindex)), None) => Ok(ContractAddress { index, subindex }),
+            _ => Err(ParseContractAddressError),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type", content = "address")]
 pub enum Address {
@@ -138,6 +462,15 @@ pub enum Address {
     Contract(ContractAddress),
 }
 
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Address::Account(account) => write!(f, "{}", account),
+            Address::Contract(contract) => write!(f, "{}", contract),
+        }
+    }
+}
+
 /// Special transaction outcomes.
 /// More info: https://git.io/J0thA
 #[derive(Deserialize, Debug)]
@@ -148,8 +481,47 @@ pub enum OutcomeKind {
         baker_rewards: Vec<AddressWithAmount>,
     },
     Mint,
-    FinalizationRewards,
-    BlockReward,
+    #[serde(rename_all = "camelCase")]
+    FinalizationRewards {
+        finalization_rewards: Vec<AddressWithAmount>,
+    },
+    #[serde(rename_all = "camelCase")]
+    BlockReward {
+        transaction_fees: Amount,
+        baker_reward: Amount,
+        foundation_charge: Amount,
+        baker: AccountAddress,
+        foundation_account: AccountAddress,
+    },
+    #[serde(rename_all = "camelCase")]
+    PaydayFoundationReward {
+        foundation_account: AccountAddress,
+        development_charge: Amount,
+    },
+    #[serde(rename_all = "camelCase")]
+    PaydayAccountReward {
+        account: AccountAddress,
+        transaction_fees: Amount,
+        baker_reward: Amount,
+        finalization_reward: Amount,
+    },
+    BlockAccrueReward,
+    #[serde(rename_all = "camelCase")]
+    PaydayPoolReward {
+        pool_owner: Option<u64>,
+        transaction_fees: Amount,
+        baker_reward: Amount,
+        finalization_reward: Amount,
+    },
+    /// Outcome introduced after this bot was written.
+    #[serde(skip_deserializing)]
+    Unknown(Value),
+}
+
+impl Unrecognized for OutcomeKind {
+    fn unrecognized(value: Value) -> Self {
+        OutcomeKind::Unknown(value)
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -0,0 +1,254 @@
+use super::{tolerant::Unrecognized, AccountAddress, Address, Amount, ContractAddress};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+
+/// Reasons why a transaction was rejected.
+/// Variants with positional fields carry them in `contents`.
+/// More info: https://git.io/J9cQA
+#[derive(Deserialize, Debug)]
+#[serde(tag = "tag")]
+pub enum RejectReason {
+    ModuleNotWF,
+    ModuleHashAlreadyExists {
+        contents: String,
+    },
+    InvalidAccountReference {
+        contents: AccountAddress,
+    },
+    InvalidInitMethod {
+        contents: (String, String),
+    },
+    InvalidReceiveMethod {
+        contents: (String, String),
+    },
+    InvalidModuleReference {
+        contents: String,
+    },
+    InvalidContractAddress {
+        contents: ContractAddress,
+    },
+    RuntimeFailure,
+    AmountTooLarge {
+        contents: (Address, Amount),
+    },
+    SerializationFailure,
+    OutOfEnergy,
+    #[serde(rename_all = "camelCase")]
+    RejectedInit {
+        reject_reason: i32,
+    },
+    #[serde(rename_all = "camelCase")]
+    RejectedReceive {
+        reject_reason: i32,
+        contract_address: ContractAddress,
+        receive_name: String,
+    },
+    NonExistentRewardAccount {
+        contents: AccountAddress,
+    },
+    InvalidProof,
+    AlreadyABaker {
+        contents: u64,
+    },
+    NotABaker {
+        contents: AccountAddress,
+    },
+    InsufficientBalanceForBakerStake,
+    StakeUnderMinimumThresholdForBaking,
+    BakerInCooldown,
+    DuplicateAggregationKey {
+        contents: String,
+    },
+    NonExistentCredentialID,
+    KeyIndexAlreadyInUse,
+    InvalidAccountThreshold,
+    InvalidCredentialKeySignThreshold,
+    InvalidEncryptedAmountTransferProof,
+    InvalidTransferToPublicProof,
+    EncryptedAmountSelfTransfer {
+        contents: AccountAddress,
+    },
+    InvalidIndexOnEncryptedTransfer,
+    ZeroScheduledAmount,
+    NonIncreasingSchedule,
+    FirstScheduledReleaseExpired,
+    ScheduledSelfTransfer {
+        contents: AccountAddress,
+    },
+    InvalidCredentials,
+    DuplicateCredIDs {
+        contents: Vec<String>,
+    },
+    NonExistentCredIDs {
+        contents: Vec<String>,
+    },
+    RemoveFirstCredential,
+    CredentialHolderDidNotSign,
+    NotAllowedMultipleCredentials,
+    NotAllowedToReceiveEncrypted,
+    NotAllowedToHandleEncrypted,
+    MissingBakerAddParameters,
+    FinalizationRewardCommissionNotInRange,
+    BakingRewardCommissionNotInRange,
+    TransactionFeeCommissionNotInRange,
+    AlreadyADelegator,
+    InsufficientBalanceForDelegationStake,
+    MissingDelegationAddParameters,
+    InsufficientDelegationStake,
+    DelegatorInCooldown,
+    NotADelegator {
+        contents: AccountAddress,
+    },
+    DelegationTargetNotABaker {
+        contents: u64,
+    },
+    StakeOverMaximumThresholdForPool,
+    PoolWouldBecomeOverDelegated,
+    PoolClosed,
+    /// Reason introduced after this bot was written.
+    #[serde(skip_deserializing)]
+    Unknown(Value),
+}
+
+impl Unrecognized for RejectReason {
+    fn unrecognized(value: Value) -> Self {
+        RejectReason::Unknown(value)
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use RejectReason::*;
+
+        match self {
+            ModuleNotWF => write!(f, "smart contract module is not well-formed"),
+            ModuleHashAlreadyExists { contents } => {
+                write!(f, "module with hash {} already exists", contents)
+            }
+            InvalidAccountReference { contents } => {
+                write!(f, "account {} does not exist", contents)
+            }
+            InvalidInitMethod {
+                contents: (module, name),
+            } => write!(f, "init method {} not found in module {}", name, module),
+            InvalidReceiveMethod {
+                contents: (module, name),
+            } => write!(f, "receive method {} not found in module {}", name, module),
+            InvalidModuleReference { contents } => write!(f, "module {} does not exist", contents),
+            InvalidContractAddress { contents } => {
+                write!(f, "contract {} does not exist", contents)
+            }
+            RuntimeFailure => write!(f, "runtime failure of the smart contract"),
+            AmountTooLarge {
+                contents: (address, amount),
+            } => write!(
+                f,
+                "{} has insufficient funds to send {} CCD",
+                address, amount
+            ),
+            SerializationFailure => write!(f, "transaction payload could not be deserialized"),
+            OutOfEnergy => write!(f, "out of energy"),
+            RejectedInit { reject_reason } => {
+                write!(
+                    f,
+                    "contract initialization rejected with code {}",
+                    reject_reason
+                )
+            }
+            RejectedReceive {
+                reject_reason,
+                contract_address,
+                receive_name,
+            } => write!(
+                f,
+                "contract {} rejected {} with code {}",
+                contract_address, receive_name, reject_reason
+            ),
+            NonExistentRewardAccount { contents } => {
+                write!(f, "reward account {} does not exist", contents)
+            }
+            InvalidProof => write!(f, "invalid proof"),
+            AlreadyABaker { contents } => {
+                write!(f, "account is already a baker with ID {}", contents)
+            }
+            NotABaker { contents } => write!(f, "account {} is not a baker", contents),
+            InsufficientBalanceForBakerStake => write!(f, "insufficient balance for baker stake"),
+            StakeUnderMinimumThresholdForBaking => {
+                write!(f, "stake is under the minimum threshold for baking")
+            }
+            BakerInCooldown => write!(f, "baker is in cooldown"),
+            DuplicateAggregationKey { contents } => {
+                write!(f, "duplicate aggregation key {}", contents)
+            }
+            NonExistentCredentialID => write!(f, "credential ID does not exist"),
+            KeyIndexAlreadyInUse => write!(f, "key index is already in use"),
+            InvalidAccountThreshold => write!(f, "invalid account threshold"),
+            InvalidCredentialKeySignThreshold => {
+                write!(f, "invalid credential key signature threshold")
+            }
+            InvalidEncryptedAmountTransferProof => {
+                write!(f, "invalid encrypted amount transfer proof")
+            }
+            InvalidTransferToPublicProof => write!(f, "invalid transfer to public proof"),
+            EncryptedAmountSelfTransfer { contents } => {
+                write!(f, "encrypted amount transfer from {} to itself", contents)
+            }
+            InvalidIndexOnEncryptedTransfer => write!(f, "invalid index on encrypted transfer"),
+            ZeroScheduledAmount => write!(f, "scheduled amount is zero"),
+            NonIncreasingSchedule => write!(f, "release schedule is not strictly increasing"),
+            FirstScheduledReleaseExpired => write!(f, "first scheduled release is in the past"),
+            ScheduledSelfTransfer { contents } => {
+                write!(f, "scheduled transfer from {} to itself", contents)
+            }
+            InvalidCredentials => write!(f, "invalid credentials"),
+            DuplicateCredIDs { contents } => {
+                write!(f, "duplicate credential IDs: {}", contents.join(", "))
+            }
+            NonExistentCredIDs { contents } => {
+                write!(f, "non-existent credential IDs: {}", contents.join(", "))
+            }
+            RemoveFirstCredential => write!(f, "attempt to remove the first credential"),
+            CredentialHolderDidNotSign => write!(f, "credential holder did not sign"),
+            NotAllowedMultipleCredentials => {
+                write!(f, "account is not allowed to have multiple credentials")
+            }
+            NotAllowedToReceiveEncrypted => {
+                write!(f, "account is not allowed to receive encrypted transfers")
+            }
+            NotAllowedToHandleEncrypted => {
+                write!(f, "account is not allowed to handle encrypted transfers")
+            }
+            MissingBakerAddParameters => write!(f, "missing parameters to add a baker"),
+            FinalizationRewardCommissionNotInRange => {
+                write!(
+                    f,
+                    "finalization reward commission is not in the allowed range"
+                )
+            }
+            BakingRewardCommissionNotInRange => {
+                write!(f, "baking reward commission is not in the allowed range")
+            }
+            TransactionFeeCommissionNotInRange => {
+                write!(f, "transaction fee commission is not in the allowed range")
+            }
+            AlreadyADelegator => write!(f, "account is already a delegator"),
+            InsufficientBalanceForDelegationStake => {
+                write!(f, "insufficient balance for delegation stake")
+            }
+            MissingDelegationAddParameters => write!(f, "missing parameters to add a delegator"),
+            InsufficientDelegationStake => write!(f, "delegation stake is insufficient"),
+            DelegatorInCooldown => write!(f, "delegator is in cooldown"),
+            NotADelegator { contents } => write!(f, "account {} is not a delegator", contents),
+            DelegationTargetNotABaker { contents } => {
+                write!(f, "delegation target {} is not a baker", contents)
+            }
+            StakeOverMaximumThresholdForPool => {
+                write!(f, "stake is over the maximum threshold for the pool")
+            }
+            PoolWouldBecomeOverDelegated => write!(f, "pool would become over-delegated"),
+            PoolClosed => write!(f, "pool is closed for new delegators"),
+            Unknown(value) => write!(f, "unrecognized reason {}", value["tag"]),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use super::Amount;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+pub struct ParseDirectionError;
+
+pub struct ParseCategoryError;
+
+impl fmt::Display for ParseCategoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid category")
+    }
+}
+
+impl fmt::Display for ParseDirectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid direction")
+    }
+}
+
+/// Direction of transfers a subscriber wants to be notified about.
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[sqlx(type_name = "direction", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+    Both,
+}
+
+impl Direction {
+    pub fn variants() -> [Direction; 3] {
+        [Direction::Incoming, Direction::Outgoing, Direction::Both]
+    }
+
+    /// Returns `true` if a transfer in `direction` matches this setting.
+    /// Transfer to self is both incoming and outgoing, so it matches any setting.
+    pub fn accepts(&self, direction: Direction) -> bool {
+        *self == Direction::Both || direction == Direction::Both || *self == direction
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self {
+            Direction::Incoming => "incoming",
+            Direction::Outgoing => "outgoing",
+            Direction::Both => "both",
+        };
+        write!(f, "{}", direction)
+    }
+}
+
+impl FromStr for Direction {
+    type Err = ParseDirectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "incoming" => Ok(Direction::Incoming),
+            "outgoing" => Ok(Direction::Outgoing),
+            "both" => Ok(Direction::Both),
+            _ => Err(ParseDirectionError),
+        }
+    }
+}
+
+/// Category of events a subscriber can enable or disable notifications about.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Transfers,
+    ScheduledTransfers,
+    ShieldedTransfers,
+    Rewards,
+    Staking,
+    Rejections,
+    Credentials,
+    Data,
+    Tokens,
+    Other,
+}
+
+impl Category {
+    pub fn variants() -> [Category; 10] {
+        use Category::*;
+        [
+            Transfers,
+            ScheduledTransfers,
+            ShieldedTransfers,
+            Rewards,
+            Staking,
+            Rejections,
+            Credentials,
+            Data,
+            Tokens,
+            Other,
+        ]
+    }
+
+    /// Returns the name of category stored in database, e.g. "scheduled_transfers".
+    pub fn key(&self) -> &'static str {
+        match self {
+            Category::Transfers => "transfers",
+            Category::ScheduledTransfers => "scheduled_transfers",
+            Category::ShieldedTransfers => "shielded_transfers",
+            Category::Rewards => "rewards",
+            Category::Staking => "staking",
+            Category::Rejections => "rejections",
+            Category::Credentials => "credentials",
+            Category::Data => "data",
+            Category::Tokens => "tokens",
+            Category::Other => "other",
+        }
+    }
+
+    /// Returns `true` for events which are notified about even during quiet hours.
+    pub fn is_critical(&self) -> bool {
+        matches!(self, Category::Rejections | Category::Credentials)
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let category = match self {
+            Category::Transfers => "Transfers",
+            Category::ScheduledTransfers => "Scheduled transfers",
+            Category::ShieldedTransfers => "Shielded transfers",
+            Category::Rewards => "Rewards",
+            Category::Staking => "Baker and delegation changes",
+            Category::Rejections => "Rejected transactions",
+            Category::Credentials => "Credential changes",
+            Category::Data => "Registered data",
+            Category::Tokens => "Tokens",
+            Category::Other => "Other events",
+        };
+        write!(f, "{}", category)
+    }
+}
+
+impl FromStr for Category {
+    type Err = ParseCategoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Category::variants()
+            .iter()
+            .find(|category| category.key() == s)
+            .copied()
+            .ok_or(ParseCategoryError)
+    }
+}
+
+/// Subscription settings of a Telegram user for an account, cached in Redis.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Subscriber {
+    pub user_id: i64,
+    pub direction: Direction,
+    /// Minimum amount of transfers to be notified about.
+    #[serde(default)]
+    pub threshold: Option<Amount>,
+    #[serde(default)]
+    pub disabled_categories: Vec<Category>,
+}
+
+impl Subscriber {
+    /// Returns `true` if notifications about events of `category` are enabled.
+    pub fn accepts(&self, category: Category) -> bool {
+        !self.disabled_categories.contains(&category)
+    }
+}
@@ -0,0 +1,46 @@
+//! Deserializers which don't fail on unrecognized values, e.g. introduced by a protocol update.
+//! Such values are kept as raw JSON in a fallback variant instead of failing the whole summary.
+use log::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+/// Types with a fallback variant for unrecognized values.
+pub trait Unrecognized {
+    fn unrecognized(value: Value) -> Self;
+}
+
+fn recognize<T>(value: Value) -> T
+where
+    T: DeserializeOwned + Unrecognized,
+{
+    T::deserialize(&value).unwrap_or_else(|err| {
+        warn!("Unrecognized value {}: {}", value, err);
+        T::unrecognized(value)
+    })
+}
+
+pub fn value<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Unrecognized,
+{
+    Ok(recognize(Value::deserialize(deserializer)?))
+}
+
+pub fn option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Unrecognized,
+{
+    Ok(Option::<Value>::deserialize(deserializer)?.map(recognize))
+}
+
+pub fn vec<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Unrecognized,
+{
+    let values = Vec::<Value>::deserialize(deserializer)?;
+    Ok(values.into_iter().map(recognize).collect())
+}
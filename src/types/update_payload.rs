@@ -0,0 +1,117 @@
+use super::{tolerant::Unrecognized, AccountAddress};
+use crate::utils::format_percentage;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+
+/// Chain parameter updates enqueued by governance.
+/// Update types not listed here are kept as raw JSON in `Unknown`.
+/// More info: https://git.io/J9cQA
+#[derive(Deserialize, Debug)]
+#[serde(tag = "updateType", content = "update", rename_all = "camelCase")]
+pub enum UpdatePayload {
+    Protocol(ProtocolUpdate),
+    ElectionDifficulty(f64),
+    EuroPerEnergy(ExchangeRate),
+    #[serde(rename = "microGTUPerEuro")]
+    MicroGtuPerEuro(ExchangeRate),
+    FoundationAccount(AccountAddress),
+    #[serde(alias = "mintDistributionCPV1")]
+    MintDistribution(MintDistribution),
+    TransactionFeeDistribution(TransactionFeeDistribution),
+    #[serde(skip_deserializing)]
+    Unknown(Value),
+}
+
+impl Unrecognized for UpdatePayload {
+    fn unrecognized(value: Value) -> Self {
+        UpdatePayload::Unknown(value)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtocolUpdate {
+    pub message: String,
+    #[serde(rename = "specificationURL")]
+    pub specification_url: String,
+    pub specification_hash: String,
+}
+
+/// Exchange rate as a fraction.
+#[derive(Deserialize, Debug)]
+pub struct ExchangeRate {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl fmt::Display for ExchangeRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// Distribution of newly minted CCD, given as fractions.
+/// Mint per slot is missing since protocol version 4.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MintDistribution {
+    pub mint_per_slot: Option<f64>,
+    pub baking_reward: f64,
+    pub finalization_reward: f64,
+}
+
+/// Distribution of transaction fees, given as fractions.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionFeeDistribution {
+    pub baker: f64,
+    pub gas_account: f64,
+}
+
+impl fmt::Display for UpdatePayload {
+    /// Formats update name and the new value on separate lines.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use UpdatePayload::*;
+
+        match self {
+            Protocol(update) => write!(
+                f,
+                "Protocol update\nMessage: {}\nSpecification: {}\nSpecification hash: {}",
+                update.message, update.specification_url, update.specification_hash
+            ),
+            ElectionDifficulty(difficulty) => {
+                write!(f, "Election difficulty\nNew value: {}", difficulty)
+            }
+            EuroPerEnergy(rate) => write!(f, "Euro per energy rate\nNew rate: {}", rate),
+            MicroGtuPerEuro(rate) => write!(f, "microCCD per euro rate\nNew rate: {}", rate),
+            FoundationAccount(account) => {
+                write!(f, "Foundation account\nNew account: {}", account)
+            }
+            MintDistribution(distribution) => {
+                write!(f, "Mint distribution")?;
+                if let Some(mint_per_slot) = distribution.mint_per_slot {
+                    write!(f, "\nMint per slot: {:?}", mint_per_slot)?;
+                }
+                write!(
+                    f,
+                    "\nBaking reward: {}\nFinalization reward: {}",
+                    format_percentage(distribution.baking_reward),
+                    format_percentage(distribution.finalization_reward)
+                )
+            }
+            TransactionFeeDistribution(distribution) => write!(
+                f,
+                "Transaction fee distribution\nBaker: {}\nGAS account: {}",
+                format_percentage(distribution.baker),
+                format_percentage(distribution.gas_account)
+            ),
+            Unknown(value) => write!(
+                f,
+                "Update {}\nNew value: {}",
+                value["updateType"].as_str().unwrap_or("unknown"),
+                value["update"]
+            ),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use super::{AccountAddress, Amount, Category, Direction};
+use chrono::{DateTime, Duration, FixedOffset, NaiveTime, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+pub struct ParseDeliveryModeError;
+
+pub struct ParseQuietModeError;
+
+impl fmt::Display for ParseQuietModeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid quiet mode")
+    }
+}
+
+impl fmt::Display for ParseDeliveryModeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid delivery mode")
+    }
+}
+
+/// How notifications are delivered to a Telegram user.
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[sqlx(type_name = "delivery_mode", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryMode {
+    /// Each notification is sent as soon as the event happens.
+    Immediate,
+    /// Events are aggregated into a digest sent at the beginning of each hour.
+    Hourly,
+    /// Events are aggregated into a digest sent once a day at the chosen time.
+    Daily,
+}
+
+impl DeliveryMode {
+    pub fn variants() -> [DeliveryMode; 3] {
+        [
+            DeliveryMode::Immediate,
+            DeliveryMode::Hourly,
+            DeliveryMode::Daily,
+        ]
+    }
+}
+
+impl fmt::Display for DeliveryMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = match self {
+            DeliveryMode::Immediate => "immediate",
+            DeliveryMode::Hourly => "hourly",
+            DeliveryMode::Daily => "daily",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+impl FromStr for DeliveryMode {
+    type Err = ParseDeliveryModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "immediate" => Ok(DeliveryMode::Immediate),
+            "hourly" => Ok(DeliveryMode::Hourly),
+            "daily" => Ok(DeliveryMode::Daily),
+            _ => Err(ParseDeliveryModeError),
+        }
+    }
+}
+
+/// How notifications are handled during quiet hours.
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[sqlx(type_name = "quiet_mode", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum QuietMode {
+    /// Notifications are sent without sound.
+    Silent,
+    /// Notifications are held until quiet hours end.
+    Defer,
+}
+
+impl fmt::Display for QuietMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = match self {
+            QuietMode::Silent => "silent",
+            QuietMode::Defer => "defer",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+impl FromStr for QuietMode {
+    type Err = ParseQuietModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "silent" => Ok(QuietMode::Silent),
+            "defer" => Ok(QuietMode::Defer),
+            _ => Err(ParseQuietModeError),
+        }
+    }
+}
+
+/// Quiet hours of a Telegram user, cached in Redis.
+/// Start and end are given in seconds since local midnight, offset from UTC in seconds.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuietHours {
+    pub start: u32,
+    pub end: u32,
+    pub utc_offset: i32,
+    pub mode: QuietMode,
+}
+
+impl QuietHours {
+    pub fn new(start: NaiveTime, end: NaiveTime, utc_offset: FixedOffset, mode: QuietMode) -> Self {
+        Self {
+            start: start.num_seconds_from_midnight(),
+            end: end.num_seconds_from_midnight(),
+            utc_offset: utc_offset.local_minus_utc(),
+            mode,
+        }
+    }
+
+    /// Returns `true` if `now` is within quiet hours, which may span midnight.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        let offset = FixedOffset::east_opt(self.utc_offset).unwrap_or_else(|| FixedOffset::east(0));
+        let time = now
+            .with_timezone(&offset)
+            .time()
+            .num_seconds_from_midnight();
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let time = |secs| NaiveTime::from_num_seconds_from_midnight(secs, 0).format("%H:%M");
+        let offset = FixedOffset::east_opt(self.utc_offset).unwrap_or_else(|| FixedOffset::east(0));
+        write!(
+            f,
+            "{}-{} UTC{}, {}",
+            time(self.start),
+            time(self.end),
+            offset,
+            self.mode
+        )
+    }
+}
+
+/// Parses offset from UTC formatted as `+HH:MM`, `-HH:MM` or `+HH`, e.g. "+02:00".
+pub fn parse_utc_offset(s: &str) -> Option<FixedOffset> {
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = s[1..].split_once(':').unwrap_or((&s[1..], "0"));
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parses local time of day formatted as `HH:MM`, e.g. "09:00".
+pub fn parse_time_of_day(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M").ok()
+}
+
+/// Digest delivery settings of a Telegram user.
+#[derive(Debug)]
+pub struct DigestSettings {
+    pub user_id: i64,
+    pub delivery_mode: DeliveryMode,
+    pub utc_offset: FixedOffset,
+    /// Local time of daily digests.
+    pub digest_time: NaiveTime,
+    /// When the last digest was sent or the delivery mode was changed.
+    pub last_digest_at: Option<DateTime<Utc>>,
+}
+
+impl DigestSettings {
+    /// Reads offset from UTC and local time of day given in seconds.
+    pub fn new(
+        user_id: i64,
+        delivery_mode: DeliveryMode,
+        utc_offset: i32,
+        digest_time: i32,
+        last_digest_at: Option<i64>,
+    ) -> Self {
+        Self {
+            user_id,
+            delivery_mode,
+            utc_offset: FixedOffset::east_opt(utc_offset).unwrap_or_else(|| FixedOffset::east(0)),
+            digest_time: NaiveTime::from_num_seconds_from_midnight_opt(digest_time as u32, 0)
+                .unwrap_or_else(|| NaiveTime::from_hms(0, 0, 0)),
+            last_digest_at: last_digest_at.map(|timestamp| Utc.timestamp(timestamp, 0)),
+        }
+    }
+
+    /// Returns the latest time a digest is scheduled at, which is not later than `now`.
+    /// Pending events of users who switched back to immediate delivery are due right away.
+    pub fn scheduled_before(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let local = now.with_timezone(&self.utc_offset);
+        let scheduled = match self.delivery_mode {
+            DeliveryMode::Immediate => return now,
+            DeliveryMode::Hourly => local.date().and_hms(local.hour(), 0, 0),
+            DeliveryMode::Daily => {
+                let today = local.date().and_time(self.digest_time).unwrap();
+                if today > local {
+                    today - Duration::days(1)
+                } else {
+                    today
+                }
+            }
+        };
+        scheduled.with_timezone(&Utc)
+    }
+
+    /// Returns `true` if a digest wasn't sent since the latest scheduled time.
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        match self.last_digest_at {
+            Some(last_digest_at) => last_digest_at < self.scheduled_before(now),
+            None => true,
+        }
+    }
+}
+
+/// Event accumulated for a digest instead of being sent immediately.
+#[derive(Clone, Debug)]
+pub struct DigestEvent {
+    pub account: AccountAddress,
+    pub category: Category,
+    /// Direction of a transfer, events are summed up for each direction separately.
+    pub direction: Option<Direction>,
+    /// Amount of CCD, summed up in the digest.
+    pub amount: Option<Amount>,
+}
@@ -1,192 +1,1779 @@
-use crate::{db, redis_cm, sender::Message, types::*, utils::*};
+use crate::cis2::{self, TokenEvent};
+use crate::{address_book, db, redis_cm, sender::Message, types::*, utils::*};
 use base58check::ToBase58Check;
+use chrono::DateTime;
 use log::*;
-use redis::{aio::ConnectionManager, AsyncCommands};
+use redis::aio::ConnectionManager;
 use sqlx::postgres::PgListener;
+use std::future::Future;
+use teloxide::utils::html;
 use tokio::sync::mpsc::Sender;
 
 const TX_CHANNEL: &str = "tx_channel";
+const CONTRACT_TX_CHANNEL: &str = "contract_tx_channel";
+const CHAIN_UPDATE_CHANNEL: &str = "chain_update_channel";
+const POOL_CHANNEL: &str = "pool_channel";
 
+/// Maximum number of hex digits shown for a single contract event log.
+const MAX_LOG_LEN: usize = 256;
+
+/// Duration of an epoch in milliseconds, the same on mainnet and testnet.
+const EPOCH_DURATION: u64 = 60 * 60 * 1000;
+
+/// Env variables with cooldown periods of bakers and delegators in epochs.
+const BAKER_COOLDOWN: &str = "CONCORDIUM_BAKER_COOLDOWN_EPOCHS";
+const DELEGATOR_COOLDOWN: &str = "CONCORDIUM_DELEGATOR_COOLDOWN_EPOCHS";
+
+/// Number of updates loaded at once when catching up.
+const PAGE_SIZE: i64 = 500;
+
+/// Handles updates missed since the last handled transaction indexes,
+/// then account, contract and chain updates via PostgreSQL pub/sub channels.
 pub async fn handle_updates(tx: Sender<Message>) -> Result<(), sqlx::Error> {
     let mut cm = redis_cm().await.clone();
     let mut listener = PgListener::connect(&env("POSTGRESQL_URL")).await?;
 
-    info!("Listening channel {}", TX_CHANNEL);
-    listener.listen(TX_CHANNEL).await?;
+    info!(
+        "Listening channels {}, {}, {}, {}",
+        TX_CHANNEL, CONTRACT_TX_CHANNEL, CHAIN_UPDATE_CHANNEL, POOL_CHANNEL
+    );
+    listener
+        .listen_all(vec![
+            TX_CHANNEL,
+            CONTRACT_TX_CHANNEL,
+            CHAIN_UPDATE_CHANNEL,
+            POOL_CHANNEL,
+        ])
+        .await?;
+
+    // Notifications about updates inserted while catching up are buffered by the listener,
+    // those already handled by the catch-up are skipped
+    let last_ati = catch_up(
+        "ati",
+        "account updates",
+        db::account_updates_since,
+        |(index_id, address, block_time, summary): (i64, String, i64, String)| {
+            let (tx, mut cm) = (tx.clone(), cm.clone());
+            async move {
+                let block_time = block_time as u64;
+                handle_account_summary(&tx, index_id, address, block_time, &summary, &mut cm).await;
+                index_id
+            }
+        },
+    )
+    .await?;
+    let last_cti = catch_up(
+        "cti",
+        "contract updates",
+        db::contract_updates_since,
+        |(index_id, contract, summary): (i64, ContractAddress, String)| {
+            let (tx, mut cm) = (tx.clone(), cm.clone());
+            async move {
+                handle_contract_summary(&tx, index_id, contract, &summary, &mut cm).await;
+                index_id
+            }
+        },
+    )
+    .await?;
+    let last_chain_update = catch_up(
+        "chain_updates",
+        "chain updates",
+        db::chain_updates_since,
+        |(id, summary): (i64, String)| {
+            let (tx, mut cm) = (tx.clone(), cm.clone());
+            async move {
+                handle_chain_update_summary(&tx, id, &summary, &mut cm).await;
+                id
+            }
+        },
+    )
+    .await?;
+    let last_pool_update = catch_up(
+        "pool_updates",
+        "pool updates",
+        db::pool_updates_since,
+        |(id, summary): (i64, String)| {
+            let (tx, mut cm) = (tx.clone(), cm.clone());
+            async move {
+                handle_pool_update_summary(&tx, id, &summary, &mut cm).await;
+                id
+            }
+        },
+    )
+    .await?;
 
     loop {
         let n11 = listener.recv().await?;
-        let (index_id, address, summary) = parse_payload(n11.payload());
-
-        match serde_json::from_str(summary) {
-            Ok(summary) => {
-                let update = AccountUpdate {
-                    index_id,
-                    account: AccountAddress::new(address),
-                    summary,
-                };
-                debug!("{:?}", update);
-                handle_update(&tx, update, &mut cm).await;
+
+        if n11.channel() == CONTRACT_TX_CHANNEL {
+            let (index_id, contract, summary) = parse_contract_payload(n11.payload());
+            if !is_handled(index_id, last_cti) {
+                handle_contract_summary(&tx, index_id, contract, summary, &mut cm).await;
+            }
+        } else if n11.channel() == CHAIN_UPDATE_CHANNEL {
+            let (id, summary) = parse_summary_payload(n11.payload());
+            if !is_handled(id, last_chain_update) {
+                handle_chain_update_summary(&tx, id, summary, &mut cm).await;
             }
-            Err(err) => {
-                error!("{}", err);
-                debug!("index_id: {} summary: {}", index_id, summary);
+        } else if n11.channel() == POOL_CHANNEL {
+            let (id, summary) = parse_summary_payload(n11.payload());
+            if !is_handled(id, last_pool_update) {
+                handle_pool_update_summary(&tx, id, summary, &mut cm).await;
+            }
+        } else {
+            let (index_id, address, block_time, summary) = parse_payload(n11.payload());
+            if !is_handled(index_id, last_ati) {
+                handle_account_summary(&tx, index_id, address, block_time, summary, &mut cm).await;
             }
         }
     }
 }
 
-/// Processes account updates since last handled account transaction index.
-pub async fn process_updates_since_last_ati(tx: Sender<Message>) {
-    let mut cm = redis_cm().await.clone();
-    let index_id: Option<i64> = cm.get("ati:latest").await.unwrap();
-
-    if let Some(index_id) = index_id {
-        info!("Last account transaction index ID {}", index_id);
-        let updates = db::account_updates_since(index_id).await.unwrap();
-
-        if updates.len() > 0 {
-            info!("Processing {} account updates", updates.len());
-            for (index_id, address, summary) in updates {
-                match serde_json::from_str(&summary) {
-                    Ok(summary) => {
-                        let update = AccountUpdate {
-                            index_id,
-                            account: AccountAddress::new(address),
-                            summary,
-                        };
-                        handle_update(&tx, update, &mut cm).await;
-                    }
-                    Err(err) => {
-                        error!("{}", err);
-                        debug!("index_id: {} summary: {}", index_id, summary);
-                    }
-                }
-            }
-        } else {
-            info!("No account updates found");
+/// Returns `true` if update with ID was handled while catching up to `last_id`.
+fn is_handled(id: i64, last_id: Option<i64>) -> bool {
+    match last_id {
+        Some(last_id) => id <= last_id,
+        None => false,
+    }
+}
+
+/// Processes updates since the last handled ID of `source` (`ati`, `cti`, `chain_updates`
+/// or `pool_updates`).
+/// Returns ID of the last processed update, `None` if nothing was handled before.
+async fn catch_up<T, F, FFut, H, HFut>(
+    source: &str,
+    name: &str,
+    fetch: F,
+    handle: H,
+) -> Result<Option<i64>, sqlx::Error>
+where
+    F: FnMut(i64, i64) -> FFut,
+    FFut: Future<Output = Result<Vec<T>, sqlx::Error>>,
+    H: FnMut(T) -> HFut,
+    HFut: Future<Output = i64>,
+{
+    let from = match db::latest_index_id(source).await? {
+        Some(id) => id,
+        None => {
+            info!("Last handled ID of {} not found", source);
+            return Ok(None);
+        }
+    };
+    info!("Last handled ID of {} is {}", source, from);
+
+    let (count, last_id) = process_pages(from, PAGE_SIZE, fetch, handle).await?;
+    info!("Processed {} {}", count, name);
+    Ok(Some(last_id))
+}
+
+/// Processes updates with IDs from `from` page by page. `fetch` loads up to `page_size` updates
+/// with IDs from the given one in ascending order, `handle` processes an update returning its ID.
+/// Returns the number of processed updates and ID of the last one.
+async fn process_pages<T, F, FFut, H, HFut>(
+    from: i64,
+    page_size: i64,
+    mut fetch: F,
+    mut handle: H,
+) -> Result<(usize, i64), sqlx::Error>
+where
+    F: FnMut(i64, i64) -> FFut,
+    FFut: Future<Output = Result<Vec<T>, sqlx::Error>>,
+    H: FnMut(T) -> HFut,
+    HFut: Future<Output = i64>,
+{
+    let mut count = 0;
+    let mut last_id = from;
+    let mut from = from;
+    loop {
+        let updates = fetch(from, page_size).await?;
+        let page_len = updates.len();
+
+        for update in updates {
+            last_id = handle(update).await;
+        }
+
+        count += page_len;
+        if (page_len as i64) < page_size {
+            break;
+        }
+        from = last_id + 1;
+    }
+
+    Ok((count, last_id))
+}
+
+/// Parses summary of account update and handles it.
+async fn handle_account_summary(
+    tx: &Sender<Message>,
+    index_id: i64,
+    address: String,
+    block_time: u64,
+    summary: &str,
+    cm: &mut ConnectionManager,
+) {
+    match serde_json::from_str(summary) {
+        Ok(summary) => {
+            let update = AccountUpdate {
+                index_id,
+                account: AccountAddress::new(address),
+                block_time,
+                summary,
+            };
+            debug!("{:?}", update);
+            handle_update(tx, update, cm).await;
+        }
+        Err(err) => {
+            error!("{}", err);
+            debug!("index_id: {} summary: {}", index_id, summary);
+        }
+    }
+}
+
+/// Parses summary of contract update and handles it.
+async fn handle_contract_summary(
+    tx: &Sender<Message>,
+    index_id: i64,
+    contract: ContractAddress,
+    summary: &str,
+    cm: &mut ConnectionManager,
+) {
+    match serde_json::from_str(summary) {
+        Ok(summary) => {
+            let update = ContractUpdate {
+                index_id,
+                contract,
+                summary,
+            };
+            debug!("{:?}", update);
+            handle_contract_update(tx, update, cm).await;
+        }
+        Err(err) => {
+            error!("{}", err);
+            debug!("index_id: {} summary: {}", index_id, summary);
+        }
+    }
+}
+
+/// Parses summary of chain update and handles it.
+async fn handle_chain_update_summary(
+    tx: &Sender<Message>,
+    id: i64,
+    summary: &str,
+    cm: &mut ConnectionManager,
+) {
+    match serde_json::from_str(summary) {
+        Ok(summary) => {
+            debug!("{:?}", summary);
+            handle_chain_update(tx, id, summary, cm).await;
+        }
+        Err(err) => {
+            error!("{}", err);
+            debug!("id: {} summary: {}", id, summary);
+        }
+    }
+}
+
+/// Parses summary of pool reward or delegation transaction and handles it.
+async fn handle_pool_update_summary(
+    tx: &Sender<Message>,
+    id: i64,
+    summary: &str,
+    cm: &mut ConnectionManager,
+) {
+    match serde_json::from_str(summary) {
+        Ok(summary) => {
+            debug!("{:?}", summary);
+            handle_pool_update(tx, id, summary, cm).await;
+        }
+        Err(err) => {
+            error!("{}", err);
+            debug!("id: {} summary: {}", id, summary);
+        }
+    }
+}
+
+/// Notification about an on-chain event related to a subscribed account.
+struct Notification {
+    category: Category,
+    /// Direction of a transfer, `None` for events other than transfers.
+    direction: Option<Direction>,
+    /// Amount of CCD, transferred amounts are compared against the subscriber's threshold.
+    amount: Option<Amount>,
+    /// Address of the sender of an incoming transfer, which can be saved to the address book.
+    counterparty: Option<String>,
+    text: String,
+}
+
+impl Notification {
+    fn new(category: Category, text: String) -> Self {
+        Self {
+            category,
+            direction: None,
+            amount: None,
+            counterparty: None,
+            text,
+        }
+    }
+
+    fn transfer(category: Category, direction: Direction, text: String) -> Self {
+        Self {
+            category,
+            direction: Some(direction),
+            amount: None,
+            counterparty: None,
+            text,
+        }
+    }
+
+    fn with_amount(mut self, amount: Amount) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    fn with_counterparty(mut self, address: &Address) -> Self {
+        self.counterparty = Some(address.to_string());
+        self
+    }
+
+    fn accepted_by(&self, subscriber: &Subscriber) -> bool {
+        let direction_accepted = match self.direction {
+            Some(direction) => subscriber.direction.accepts(direction),
+            None => true,
+        };
+        let above_threshold = match (self.direction, self.amount, subscriber.threshold) {
+            (Some(_), Some(amount), Some(threshold)) => amount >= threshold,
+            _ => true,
+        };
+        subscriber.accepts(self.category) && direction_accepted && above_threshold
+    }
+
+    fn digest_event(&self, account: &AccountAddress) -> DigestEvent {
+        DigestEvent {
+            account: account.clone(),
+            category: self.category,
+            direction: self.direction,
+            amount: self.amount,
         }
-    } else {
-        info!("Last account transaction index ID not found");
     }
 }
 
 /// Handles update for account.
 async fn handle_update(tx: &Sender<Message>, update: AccountUpdate, cm: &mut ConnectionManager) {
+    let AccountUpdate {
+        index_id,
+        account,
+        block_time,
+        summary,
+    } = update;
+
+    let index = TransactionIndex::Account(index_id);
+    let notifications = notifications(summary, &account, block_time).await;
+    let sent = send_notifications(tx, index, &account, notifications, 0, cm).await;
+
+    // Advance the progress even if the update was filtered out for all subscribers
+    if !sent {
+        tx.send(Message::progress(index)).await.ok();
+    }
+}
+
+/// Sends notifications to subscribers of account accepting them, numbering messages from `seq`.
+/// Returns `true` if any message was sent.
+async fn send_notifications(
+    tx: &Sender<Message>,
+    index: TransactionIndex,
+    account: &AccountAddress,
+    notifications: Vec<Notification>,
+    seq: i32,
+    cm: &mut ConnectionManager,
+) -> bool {
+    let mut sent = false;
+
+    if !notifications.is_empty() {
+        let subscribers = db::subscribers(cm, account.address()).await.unwrap();
+        for (i, notification) in notifications.into_iter().enumerate() {
+            let user_ids: Vec<i64> = subscribers
+                .iter()
+                .filter(|s| notification.accepted_by(s))
+                .map(|s| s.user_id)
+                .collect();
+
+            if !user_ids.is_empty() {
+                let event = notification.digest_event(account);
+                let mut message = Message::new(index, seq + i as i32, user_ids, notification.text)
+                    .with_digest_event(event);
+                if let Some(address) = &notification.counterparty {
+                    message = message.with_markup(address_book::save_button(address));
+                }
+                tx.send(message).await.ok();
+                sent = true;
+            }
+        }
+    }
+
+    sent
+}
+
+/// Builds notifications about events of a block summary for account.
+/// `block_time` is slot time of the block in milliseconds.
+async fn notifications(
+    summary: BlockSummary,
+    account: &AccountAddress,
+    block_time: u64,
+) -> Vec<Notification> {
     use TransactionType::*;
 
-    match update.summary {
+    let unrecognized = unrecognized_notifications(&summary);
+    let mut notifications = match summary {
         BlockSummary::TransactionSummary {
             sender,
             hash,
             cost,
             r#type:
-                TransactionSummaryType::AccountTransaction(
+                TransactionSummaryType::AccountTransaction(Some(
                     Transfer
                     | TransferWithMemo
                     | TransferWithSchedule
                     | TransferWithScheduleAndMemo
                     | Update,
-                ),
+                )),
             result: TransactionOutcome::Success { events },
             ..
-        } => match event_for(events, &update.account) {
-            Some(Event::Transferred { from, to, amount }) => {
-                if let Some(subscriber_ids) = db::subscriber_ids(cm, &update.account.to_string())
-                    .await
-                    .unwrap()
-                {
-                    let msg = format!(
-                        "Transferred {} CCD from {} to {}\nTx Hash: {}\n{}Cost: {} CCD",
+        } => {
+            let footer = format!(
+                "{}Tx Hash: {}\n{}Cost: {} CCD",
+                format_memo_line(&events),
+                format_txhash(&hash),
+                format_sender(sender.as_ref()),
+                cost
+            );
+
+            let mut notifications = Vec::new();
+            for event in events {
+                let (category, kind, amount, from, to, schedule) = match event {
+                    Event::Transferred { from, to, amount } => {
+                        (Category::Transfers, "transfer", amount, from, to, None)
+                    }
+                    Event::TransferredWithSchedule { from, to, amount } => (
+                        Category::ScheduledTransfers,
+                        "transfer with schedule",
+                        amount.total_amount(),
+                        from,
+                        to,
+                        Some(amount),
+                    ),
+                    _ => continue,
+                };
+
+                if let Some(direction) = transfer_direction(&from, &to, account) {
+                    if let (true, Some(schedule)) = (is_account(&to, account), &schedule) {
+                        store_releases(account, &hash, schedule).await;
+                    }
+
+                    let text = format!(
+                        "{} {} of {} CCD\nFrom: {}\nTo: {}\n{}{}",
+                        format_direction(direction),
+                        kind,
                         amount,
                         format_address(&from),
                         format_address(&to),
-                        format_txhash(&hash),
-                        format_sender(sender),
-                        cost
+                        schedule.as_ref().map(format_schedule).unwrap_or_default(),
+                        footer
                     );
-
-                    tx.send(Message::new(update.index_id, subscriber_ids, msg))
-                        .await
-                        .ok();
+                    let mut notification =
+                        Notification::transfer(category, direction, text).with_amount(amount);
+                    if direction == Direction::Incoming {
+                        notification = notification.with_counterparty(&from);
+                    }
+                    notifications.push(notification);
                 }
             }
-            Some(Event::TransferredWithSchedule { from, to, amount }) => {
-                if let Some(subscriber_ids) = db::subscriber_ids(cm, &update.account.to_string())
-                    .await
-                    .unwrap()
-                {
-                    let msg = format!(
-                            "Transferred with schedule {} CCD from {} to {}\nTx Hash: {}\n{}Cost: {} CCD",
-                            amount.total_amount(),
-                            format_address(&from),
-                            format_address(&to),
-                            format_txhash(&hash),
-                            format_sender(sender),
-                            cost
+            notifications
+        }
+        BlockSummary::TransactionSummary {
+            sender: Some(sender),
+            hash,
+            cost,
+            r#type:
+                TransactionSummaryType::AccountTransaction(Some(
+                    EncryptedAmountTransfer
+                    | EncryptedAmountTransferWithMemo
+                    | TransferToEncrypted
+                    | TransferToPublic,
+                )),
+            result: TransactionOutcome::Success { events },
+            ..
+        } => {
+            let footer = format!(
+                "{}Tx Hash: {}\nCost: {} CCD",
+                format_memo_line(&events),
+                format_txhash(&hash),
+                cost
+            );
+
+            events
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::NewEncryptedAmount {
+                        account: receiver, ..
+                    } => {
+                        let direction = if receiver == *account {
+                            Direction::Incoming
+                        } else if sender == *account {
+                            Direction::Outgoing
+                        } else {
+                            return None;
+                        };
+                        let text = format!(
+                            "{} shielded transfer\nFrom: {}\nTo: {}\n{}",
+                            format_direction(direction),
+                            format_account_address(&sender, true),
+                            format_account_address(&receiver, true),
+                            footer
                         );
+                        let notification =
+                            Notification::transfer(Category::ShieldedTransfers, direction, text);
+                        if direction == Direction::Incoming {
+                            let counterparty = Address::Account(sender.clone());
+                            Some(notification.with_counterparty(&counterparty))
+                        } else {
+                            Some(notification)
+                        }
+                    }
+                    Event::EncryptedSelfAmountAdded {
+                        account: shielded,
+                        amount,
+                        ..
+                    } if shielded == *account => {
+                        let text = format!("🛡 Shielded {} CCD\n{}", amount, footer);
+                        Some(Notification::new(Category::ShieldedTransfers, text))
+                    }
+                    Event::AmountAddedByDecryption {
+                        account: unshielded,
+                        amount,
+                    } if unshielded == *account => {
+                        let text = format!("🔓 Unshielded {} CCD\n{}", amount, footer);
+                        Some(Notification::new(Category::ShieldedTransfers, text))
+                    }
+                    _ => None,
+                })
+                .collect()
+        }
+        BlockSummary::TransactionSummary {
+            hash,
+            cost,
+            r#type:
+                TransactionSummaryType::AccountTransaction(Some(
+                    AddBaker
+                    | RemoveBaker
+                    | UpdateBakerStake
+                    | UpdateBakerRestakeEarnings
+                    | UpdateBakerKeys
+                    | ConfigureBaker
+                    | ConfigureDelegation,
+                )),
+            result: TransactionOutcome::Success { events },
+            ..
+        } => {
+            let footer = format!("Tx Hash: {}\nCost: {} CCD", format_txhash(&hash), cost);
 
-                    tx.send(Message::new(update.index_id, subscriber_ids.to_vec(), msg))
-                        .await
-                        .ok();
+            let mut notifications = Vec::new();
+            for event in events {
+                let text = match event {
+                    Event::BakerAdded {
+                        baker_id,
+                        account: baker,
+                        stake,
+                        restake_earnings,
+                    } if baker == *account => format!(
+                        "🥖 Baker added\nBaker ID: {}\nStake: {} CCD\nRestake earnings: {}",
+                        baker_id,
+                        stake,
+                        format_bool(restake_earnings)
+                    ),
+                    Event::BakerRemoved {
+                        baker_id,
+                        account: baker,
+                    } if baker == *account => format!(
+                        "🥖 Baker removed\nBaker ID: {}{}",
+                        baker_id,
+                        format_effective_epoch(block_time, BAKER_COOLDOWN)
+                    ),
+                    Event::BakerStakeIncreased {
+                        baker_id,
+                        account: baker,
+                        new_stake,
+                    } if baker == *account => format!(
+                        "🥖 Baker stake increased\nBaker ID: {}\nNew stake: {} CCD",
+                        baker_id, new_stake
+                    ),
+                    Event::BakerStakeDecreased {
+                        baker_id,
+                        account: baker,
+                        new_stake,
+                    } if baker == *account => format!(
+                        "🥖 Baker stake decreased\nBaker ID: {}\nNew stake: {} CCD{}",
+                        baker_id,
+                        new_stake,
+                        format_effective_epoch(block_time, BAKER_COOLDOWN)
+                    ),
+                    Event::BakerSetRestakeEarnings {
+                        baker_id,
+                        account: baker,
+                        restake_earnings,
+                    } if baker == *account => format!(
+                        "🥖 Baker restake earnings updated\nBaker ID: {}\nRestake earnings: {}",
+                        baker_id,
+                        format_bool(restake_earnings)
+                    ),
+                    Event::BakerKeysUpdated {
+                        baker_id,
+                        account: baker,
+                    } if baker == *account => {
+                        format!("🥖 Baker keys updated\nBaker ID: {}", baker_id)
+                    }
+                    Event::BakerSetOpenStatus {
+                        baker_id,
+                        account: baker,
+                        open_status,
+                    } if baker == *account => format!(
+                        "🥖 Baker pool status updated\nBaker ID: {}\nStatus: {}",
+                        baker_id, open_status
+                    ),
+                    Event::BakerSetMetadataURL {
+                        baker_id,
+                        account: baker,
+                        metadata_url,
+                    } if baker == *account => format!(
+                        "🥖 Baker metadata URL updated\nBaker ID: {}\nURL: {}",
+                        baker_id,
+                        html::escape(&metadata_url)
+                    ),
+                    Event::BakerSetTransactionFeeCommission {
+                        baker_id,
+                        account: baker,
+                        transaction_fee_commission,
+                    } if baker == *account => format!(
+                        "🥖 Baker transaction fee commission updated\nBaker ID: {}\nCommission: {}",
+                        baker_id,
+                        format_percentage(transaction_fee_commission)
+                    ),
+                    Event::BakerSetBakingRewardCommission {
+                        baker_id,
+                        account: baker,
+                        baking_reward_commission,
+                    } if baker == *account => format!(
+                        "🥖 Baker baking reward commission updated\nBaker ID: {}\nCommission: {}",
+                        baker_id,
+                        format_percentage(baking_reward_commission)
+                    ),
+                    Event::BakerSetFinalizationRewardCommission {
+                        baker_id,
+                        account: baker,
+                        finalization_reward_commission,
+                    } if baker == *account => format!(
+                        "🥖 Baker finalization reward commission updated\nBaker ID: {}\nCommission: {}",
+                        baker_id,
+                        format_percentage(finalization_reward_commission)
+                    ),
+                    Event::DelegationAdded {
+                        delegator_id,
+                        account: delegator,
+                    } if delegator == *account => {
+                        format!("🤝 Delegation added\nDelegator ID: {}", delegator_id)
+                    }
+                    Event::DelegationRemoved {
+                        delegator_id,
+                        account: delegator,
+                    } if delegator == *account => format!(
+                        "🤝 Delegation removed\nDelegator ID: {}{}",
+                        delegator_id,
+                        format_effective_epoch(block_time, DELEGATOR_COOLDOWN)
+                    ),
+                    Event::DelegationStakeIncreased {
+                        delegator_id,
+                        account: delegator,
+                        new_stake,
+                    } if delegator == *account => format!(
+                        "🤝 Delegation stake increased\nDelegator ID: {}\nNew stake: {} CCD",
+                        delegator_id, new_stake
+                    ),
+                    Event::DelegationStakeDecreased {
+                        delegator_id,
+                        account: delegator,
+                        new_stake,
+                    } if delegator == *account => format!(
+                        "🤝 Delegation stake decreased\nDelegator ID: {}\nNew stake: {} CCD{}",
+                        delegator_id,
+                        new_stake,
+                        format_effective_epoch(block_time, DELEGATOR_COOLDOWN)
+                    ),
+                    Event::DelegationSetRestakeEarnings {
+                        delegator_id,
+                        account: delegator,
+                        restake_earnings,
+                    } if delegator == *account => format!(
+                        "🤝 Delegation restake earnings updated\nDelegator ID: {}\nRestake earnings: {}",
+                        delegator_id,
+                        format_bool(restake_earnings)
+                    ),
+                    Event::DelegationSetDelegationTarget {
+                        delegator_id,
+                        account: delegator,
+                        delegation_target,
+                    } if delegator == *account => format!(
+                        "🤝 Delegation target updated\nDelegator ID: {}\nTarget: {}",
+                        delegator_id, delegation_target
+                    ),
+                    _ => continue,
+                };
+                notifications.push(Notification::new(
+                    Category::Staking,
+                    format!("{}\n{}", text, footer),
+                ));
+            }
+            notifications
+        }
+        BlockSummary::TransactionSummary {
+            hash,
+            cost,
+            r#type: TransactionSummaryType::AccountTransaction(Some(RegisterData)),
+            result: TransactionOutcome::Success { events },
+            ..
+        } => events
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::DataRegistered { data } => {
+                    let text = format!(
+                        "📝 Data registered\nData: {}\nTx Hash: {}\nCost: {} CCD",
+                        format_registered_data(&data),
+                        format_txhash(&hash),
+                        cost
+                    );
+                    Some(Notification::new(Category::Data, text))
+                }
+                _ => None,
+            })
+            .collect(),
+        BlockSummary::TransactionSummary {
+            hash,
+            r#type: TransactionSummaryType::CredentialDeploymentTransaction(credential_type),
+            result: TransactionOutcome::Success { events },
+            ..
+        } => events
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::AccountCreated { contents: created } if created == *account => {
+                    Some(format!(
+                        "🆕 Account created\nCredential type: {}",
+                        html::escape(&credential_type)
+                    ))
                 }
+                Event::CredentialDeployed {
+                    reg_id,
+                    account: deployed,
+                } if deployed == *account => Some(format!(
+                    "🔑 Credential deployed\nCredential ID: <code>{}</code>",
+                    html::escape(&reg_id)
+                )),
+                _ => None,
+            })
+            .map(|text| {
+                let text = format!("{}\nTx Hash: {}", text, format_txhash(&hash));
+                Notification::new(Category::Credentials, text)
+            })
+            .collect(),
+        BlockSummary::TransactionSummary {
+            hash,
+            cost,
+            r#type:
+                TransactionSummaryType::AccountTransaction(Some(
+                    UpdateCredentialKeys | UpdateCredentials,
+                )),
+            result: TransactionOutcome::Success { events },
+            ..
+        } => {
+            let footer = format!("Tx Hash: {}\nCost: {} CCD", format_txhash(&hash), cost);
+
+            let mut notifications = Vec::new();
+            for event in events {
+                let text = match event {
+                    Event::CredentialKeysUpdated { cred_id } => format!(
+                        "🔑 Credential keys updated\nCredential ID: <code>{}</code>",
+                        html::escape(&cred_id)
+                    ),
+                    Event::CredentialsUpdated {
+                        account: updated,
+                        new_cred_ids,
+                        removed_cred_ids,
+                        new_threshold,
+                    } if updated == *account => format!(
+                        "🔑 Credentials updated\nAdded: {}\nRemoved: {}\nThreshold: {}",
+                        format_cred_ids(&new_cred_ids),
+                        format_cred_ids(&removed_cred_ids),
+                        new_threshold
+                    ),
+                    _ => continue,
+                };
+                notifications.push(Notification::new(
+                    Category::Credentials,
+                    format!("{}\n{}", text, footer),
+                ));
             }
-            _ => {}
-        },
-        BlockSummary::SpecialTransactionOutcome(OutcomeKind::BakingRewards {
-            ref baker_rewards,
-        }) => {
-            let reward = baker_rewards
+            notifications
+        }
+        BlockSummary::TransactionSummary {
+            sender: Some(sender),
+            hash,
+            cost,
+            r#type: TransactionSummaryType::AccountTransaction(transaction_type),
+            result: TransactionOutcome::Reject { reject_reason },
+            ..
+        } if sender == *account => {
+            let transaction_type = match transaction_type {
+                Some(transaction_type) => transaction_type.to_string(),
+                None => "unknown".to_string(),
+            };
+            let text = format!(
+                "❌ Transaction rejected\nType: {}\nReason: {}\nTx Hash: {}\nCost: {} CCD",
+                transaction_type,
+                html::escape(&reject_reason.to_string()),
+                format_txhash(&hash),
+                cost
+            );
+            vec![Notification::new(Category::Rejections, text)]
+        }
+        BlockSummary::SpecialTransactionOutcome(OutcomeKind::BakingRewards { baker_rewards }) => {
+            baker_rewards
                 .iter()
-                .find(|r| r.address == update.account.address());
-
-            if let Some(reward) = reward {
-                if let Some(subscriber_ids) = db::subscriber_ids(cm, &reward.address).await.unwrap()
-                {
-                    let msg = format!("Baker reward {} CCD", reward.amount,);
-                    tx.send(Message::new(update.index_id, subscriber_ids.to_vec(), msg))
-                        .await
-                        .ok();
+                .filter(|r| r.address == account.address())
+                .map(|r| {
+                    Notification::new(Category::Rewards, format!("Baker reward {} CCD", r.amount))
+                        .with_amount(r.amount)
+                })
+                .collect()
+        }
+        BlockSummary::SpecialTransactionOutcome(OutcomeKind::FinalizationRewards {
+            finalization_rewards,
+        }) => finalization_rewards
+            .iter()
+            .filter(|r| r.address == account.address())
+            .map(|r| {
+                Notification::new(
+                    Category::Rewards,
+                    format!("Finalization reward {} CCD", r.amount),
+                )
+                .with_amount(r.amount)
+            })
+            .collect(),
+        BlockSummary::SpecialTransactionOutcome(OutcomeKind::PaydayAccountReward {
+            account: rewarded,
+            transaction_fees,
+            baker_reward,
+            finalization_reward,
+        }) if rewarded == *account => {
+            let total = baker_reward + finalization_reward + transaction_fees;
+            let text = format!(
+                "💰 Payday reward {} CCD\nBaking: {} CCD\nFinalization: {} CCD\nTransaction fees: {} CCD",
+                total, baker_reward, finalization_reward, transaction_fees
+            );
+            vec![Notification::new(Category::Rewards, text).with_amount(total)]
+        }
+        BlockSummary::SpecialTransactionOutcome(OutcomeKind::PaydayFoundationReward {
+            foundation_account,
+            development_charge,
+        }) if foundation_account == *account => {
+            let text = format!("💰 Payday development charge {} CCD", development_charge);
+            vec![Notification::new(Category::Rewards, text).with_amount(development_charge)]
+        }
+        BlockSummary::SpecialTransactionOutcome(OutcomeKind::BlockReward {
+            transaction_fees,
+            baker_reward,
+            foundation_charge,
+            baker,
+            foundation_account,
+        }) => {
+            let mut notifications = Vec::new();
+            if baker == *account {
+                let text = format!(
+                    "Block reward {} CCD\nTransaction fees: {} CCD",
+                    baker_reward, transaction_fees
+                );
+                notifications
+                    .push(Notification::new(Category::Rewards, text).with_amount(baker_reward));
+            }
+            if foundation_account == *account {
+                let text = format!("Foundation charge {} CCD", foundation_charge);
+                notifications.push(
+                    Notification::new(Category::Rewards, text).with_amount(foundation_charge),
+                );
+            }
+            notifications
+        }
+        _ => Vec::new(),
+    };
+
+    notifications.extend(unrecognized);
+    notifications
+}
+
+/// Builds notifications about transaction types, events and outcomes the bot doesn't recognize,
+/// so activity on the account isn't silently dropped after a protocol update.
+fn unrecognized_notifications(summary: &BlockSummary) -> Vec<Notification> {
+    let mut notifications = Vec::new();
+
+    match summary {
+        BlockSummary::TransactionSummary {
+            hash,
+            r#type,
+            result,
+            ..
+        } => {
+            if let TransactionSummaryType::AccountTransaction(Some(TransactionType::Unknown(
+                name,
+            ))) = r#type
+            {
+                let text = format!(
+                    "❓ Unrecognized transaction <code>{}</code> on your account\nTx Hash: {}",
+                    html::escape(name),
+                    format_txhash(hash)
+                );
+                notifications.push(Notification::new(Category::Other, text));
+            }
+
+            if let TransactionOutcome::Success { events } = result {
+                for event in events {
+                    if let Event::Unknown(value) = event {
+                        let text = format!(
+                            "❓ Unrecognized event {} on your account\nTx Hash: {}",
+                            format_raw_tag(value),
+                            format_txhash(hash)
+                        );
+                        notifications.push(Notification::new(Category::Other, text));
+                    }
                 }
             }
         }
+        BlockSummary::SpecialTransactionOutcome(OutcomeKind::Unknown(value)) => {
+            let text = format!(
+                "❓ Unrecognized special outcome {} on your account",
+                format_raw_tag(value)
+            );
+            notifications.push(Notification::new(Category::Other, text));
+        }
         _ => {}
     }
+
+    notifications
 }
 
-fn parse_payload(payload: &str) -> (i64, String, &str) {
-    let mut triple = payload.split("|");
-    let index_id: i64 = triple.next().unwrap().parse().unwrap();
-    let address = hex::decode(&triple.next().unwrap()[2..])
-        .unwrap()
-        .to_base58check(1);
-    let summary = triple.next().unwrap();
-    (index_id, address, summary)
+/// Handles update for contract.
+async fn handle_contract_update(
+    tx: &Sender<Message>,
+    update: ContractUpdate,
+    cm: &mut ConnectionManager,
+) {
+    let index = TransactionIndex::Contract(update.index_id);
+
+    let texts = contract_notifications(&update.summary, &update.contract);
+    let token_seq = texts.len();
+    let mut sent = false;
+    if !texts.is_empty() {
+        let user_ids = db::contract_subscriber_ids(cm, &update.contract)
+            .await
+            .unwrap();
+        if !user_ids.is_empty() {
+            for (seq, text) in texts.into_iter().enumerate() {
+                tx.send(Message::new(index, seq as i32, user_ids.clone(), text))
+                    .await
+                    .ok();
+            }
+            sent = true;
+        }
+    }
+
+    // Accounts sending or receiving CIS-2 tokens are notified via their subscriptions
+    let tokens = token_notifications(&update.summary, &update.contract);
+    for (seq, (account, notification)) in tokens.into_iter().enumerate() {
+        let user_ids: Vec<i64> = db::subscribers(cm, account.address())
+            .await
+            .unwrap()
+            .iter()
+            .filter(|s| notification.accepted_by(s))
+            .map(|s| s.user_id)
+            .collect();
+
+        if !user_ids.is_empty() {
+            let event = notification.digest_event(&account);
+            let seq = (token_seq + seq) as i32;
+            let message =
+                Message::new(index, seq, user_ids, notification.text).with_digest_event(event);
+            tx.send(message).await.ok();
+            sent = true;
+        }
+    }
+
+    // Advance the progress even if the update was filtered out for all subscribers
+    if !sent {
+        tx.send(Message::progress(index)).await.ok();
+    }
 }
 
-fn format_sender(sender: Option<AccountAddress>) -> String {
-    if let Some(address) = sender {
-        format!("Sender: {}\n", format_account_address(&address, false))
+/// Handles chain update with summary ID.
+async fn handle_chain_update(
+    tx: &Sender<Message>,
+    id: i64,
+    summary: BlockSummary,
+    cm: &mut ConnectionManager,
+) {
+    let index = TransactionIndex::ChainUpdate(id);
+    let texts = chain_update_notifications(&summary);
+    let user_ids = if texts.is_empty() {
+        Vec::new()
     } else {
-        String::new()
+        db::chain_update_subscriber_ids(cm).await.unwrap()
+    };
+
+    // Advance the progress even if there is nothing to notify about or nobody to notify
+    if user_ids.is_empty() {
+        tx.send(Message::progress(index)).await.ok();
+        return;
+    }
+
+    for (seq, text) in texts.into_iter().enumerate() {
+        tx.send(Message::new(index, seq as i32, user_ids.clone(), text))
+            .await
+            .ok();
+    }
+}
+
+/// Builds notifications about chain parameter updates enqueued by governance.
+fn chain_update_notifications(summary: &BlockSummary) -> Vec<String> {
+    let (hash, _, events) = match successful_events(summary) {
+        Some(result) => result,
+        None => return Vec::new(),
+    };
+
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::UpdateEnqueued {
+                effective_time,
+                payload,
+            } => Some(format!(
+                "🏛 Chain update enqueued\n{}\nEffective time: {}\nTx Hash: {}",
+                html::escape(&payload.to_string()),
+                format_timestamp(effective_time * 1000),
+                format_txhash(hash)
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Handles pool reward or delegation transaction with summary ID.
+async fn handle_pool_update(
+    tx: &Sender<Message>,
+    id: i64,
+    summary: BlockSummary,
+    cm: &mut ConnectionManager,
+) {
+    let index = TransactionIndex::PoolUpdate(id);
+    let mut sent = false;
+
+    // Pools of delegators are recorded first, notifications depend on the previous pools
+    let mut changes = Vec::new();
+    for (delegator_id, delegator, baker_id) in delegation_targets(&summary) {
+        match db::set_delegator_pool(&delegator, baker_id, id).await {
+            Ok(previous) => changes.push(PoolChange {
+                delegator_id,
+                delegator,
+                previous,
+                baker_id,
+            }),
+            Err(err) => error!("Can't set pool of delegator {}: {}", delegator, err),
+        }
+    }
+
+    for (seq, (baker_id, notification)) in pool_notifications(&summary, changes)
+        .into_iter()
+        .enumerate()
+    {
+        if let Some(owner) = db::pool_owner(cm, baker_id).await.unwrap() {
+            sent |= send_notifications(tx, index, &owner, vec![notification], seq as i32, cm).await;
+        }
+    }
+
+    // Advance the progress even if no subscribed pool was affected
+    if !sent {
+        tx.send(Message::progress(index)).await.ok();
+    }
+}
+
+/// Change of the baker pool a delegator delegates to, `None` for passive or no delegation.
+struct PoolChange {
+    delegator_id: u64,
+    delegator: AccountAddress,
+    previous: Option<u64>,
+    baker_id: Option<u64>,
+}
+
+/// Returns delegator ID, account and baker ID of the new pool of delegators changing
+/// their delegation target or removing delegation, `None` for passive or no delegation.
+fn delegation_targets(summary: &BlockSummary) -> Vec<(u64, AccountAddress, Option<u64>)> {
+    let events = match successful_events(summary) {
+        Some((_, _, events)) => events,
+        None => return Vec::new(),
+    };
+
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::DelegationSetDelegationTarget {
+                delegator_id,
+                account,
+                delegation_target,
+            } => {
+                let baker_id = match delegation_target {
+                    DelegationTarget::Baker { baker_id } => Some(*baker_id),
+                    DelegationTarget::Passive => None,
+                };
+                Some((*delegator_id, account.clone(), baker_id))
+            }
+            Event::DelegationRemoved {
+                delegator_id,
+                account,
+            } => Some((*delegator_id, account.clone(), None)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Builds notifications for owners of baker pools about pool rewards and delegators
+/// joining or leaving pools. Returns them along with baker ID of the pool.
+fn pool_notifications(
+    summary: &BlockSummary,
+    changes: Vec<PoolChange>,
+) -> Vec<(u64, Notification)> {
+    match summary {
+        BlockSummary::SpecialTransactionOutcome(OutcomeKind::PaydayPoolReward {
+            pool_owner: Some(baker_id),
+            transaction_fees,
+            baker_reward,
+            finalization_reward,
+        }) => {
+            let total = *baker_reward + *finalization_reward + *transaction_fees;
+            let text = format!(
+                "💰 Pool reward {} CCD\nBaker ID: {}\nBaking: {} CCD\nFinalization: {} CCD\nTransaction fees: {} CCD",
+                total, baker_id, baker_reward, finalization_reward, transaction_fees
+            );
+            vec![(
+                *baker_id,
+                Notification::new(Category::Rewards, text).with_amount(total),
+            )]
+        }
+        BlockSummary::TransactionSummary { hash, .. } => {
+            let mut notifications = Vec::new();
+            for change in changes {
+                if change.previous == change.baker_id {
+                    continue;
+                }
+
+                let footer = format!(
+                    "Delegator ID: {}\nDelegator: {}\nTx Hash: {}",
+                    change.delegator_id,
+                    format_account_address(&change.delegator, true),
+                    format_txhash(hash)
+                );
+                if let Some(previous) = change.previous {
+                    let text = format!(
+                        "🤝 Delegator left your pool\nBaker ID: {}\n{}",
+                        previous, footer
+                    );
+                    notifications.push((previous, Notification::new(Category::Staking, text)));
+                }
+                if let Some(baker_id) = change.baker_id {
+                    let text = format!(
+                        "🤝 Delegator joined your pool\nBaker ID: {}\n{}",
+                        baker_id, footer
+                    );
+                    notifications.push((baker_id, Notification::new(Category::Staking, text)));
+                }
+            }
+            notifications
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Returns transaction hash, cost and events of a successful transaction summary.
+fn successful_events(summary: &BlockSummary) -> Option<(&str, &Amount, &[Event])> {
+    match summary {
+        BlockSummary::TransactionSummary {
+            hash,
+            cost,
+            result: TransactionOutcome::Success { events },
+            ..
+        } => Some((hash.as_str(), cost, events.as_slice())),
+        _ => None,
     }
 }
 
-fn event_for(events: Vec<Event>, address: &AccountAddress) -> Option<Event> {
-    use Event::*;
+/// Builds notification texts about events of a block summary for contract.
+fn contract_notifications(summary: &BlockSummary, contract: &ContractAddress) -> Vec<String> {
+    let (hash, cost, events) = match successful_events(summary) {
+        Some(summary) => summary,
+        None => return Vec::new(),
+    };
+
+    let footer = format!("Tx Hash: {}\nCost: {} CCD", format_txhash(hash), cost);
+
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::ContractInitialized {
+                module_ref,
+                address,
+                amount,
+                init_name,
+                events,
+            } if address == contract => {
+                let text = format!(
+                    "{} Contract {} initialized\nModule: <code>{}</code>\nInit: {}\nAmount: {} CCD\n{}{}",
+                    Emoji::Contract,
+                    html::escape(&address.to_string()),
+                    module_ref,
+                    html::escape(init_name),
+                    amount,
+                    format_logs(events),
+                    footer
+                );
+                Some(text)
+            }
+            Event::Updated {
+                address,
+                instigator,
+                amount,
+                receive_name,
+                events,
+                ..
+            } if address == contract => {
+                let text = format!(
+                    "{} Contract {} updated\nEntrypoint: {}\nAmount: {} CCD\nSender: {}\n{}{}",
+                    Emoji::Contract,
+                    html::escape(&address.to_string()),
+                    html::escape(receive_name),
+                    amount,
+                    format_address(instigator),
+                    format_logs(events),
+                    footer
+                );
+                Some(text)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Builds notifications about CIS-2 token events logged by contract
+/// for accounts sending, receiving or owning tokens.
+/// Any contract can log bytes looking like a token event, so events are trusted only if
+/// all logs of the contract in the transaction are CIS-2 events.
+fn token_notifications(
+    summary: &BlockSummary,
+    contract: &ContractAddress,
+) -> Vec<(AccountAddress, Notification)> {
+    let (hash, cost, events) = match successful_events(summary) {
+        Some(summary) => summary,
+        None => return Vec::new(),
+    };
+
+    let footer = format!("Tx Hash: {}\nCost: {} CCD", format_txhash(hash), cost);
+    let logs = events.iter().filter_map(|event| match event {
+        Event::Updated {
+            address, events, ..
+        } if address == contract => Some(events),
+        _ => None,
+    });
+
+    let token_events: Option<Vec<TokenEvent>> =
+        logs.flatten().map(|log| cis2::decode(log)).collect();
+    let token_events = match token_events {
+        Some(token_events) => token_events,
+        None => return Vec::new(),
+    };
+
+    let mut notifications = Vec::new();
+    for event in token_events {
+        let text = format!(
+            "{}\nContract: {}\n{}",
+            format_token_event(&event),
+            html::escape(&contract.to_string()),
+            footer
+        );
 
-    let mut iter = events.into_iter();
-    while let Some(event) = iter.next() {
         match event {
-            Transferred { ref to, .. } | TransferredWithSchedule { ref to, .. } => {
-                if let Address::Account(account) = to {
-                    if *address == *account {
-                        return Some(event);
+            TokenEvent::Transfer { from, to, .. } => {
+                // Transfer to self is notified about once
+                let mut accounts: Vec<&AccountAddress> = Vec::new();
+                for address in &[&to, &from] {
+                    if let Address::Account(account) = address {
+                        if !accounts.contains(&account) {
+                            accounts.push(account);
+                        }
                     }
                 }
+
+                for account in accounts {
+                    if let Some(direction) = transfer_direction(&from, &to, account) {
+                        let text =
+                            format!("{} token transfer\n{}", format_direction(direction), text);
+                        let notification =
+                            Notification::transfer(Category::Tokens, direction, text);
+                        notifications.push((account.clone(), notification));
+                    }
+                }
+            }
+            TokenEvent::Mint {
+                owner: Address::Account(account),
+                ..
+            } => {
+                let text = format!("🪙 Tokens minted\n{}", text);
+                notifications.push((
+                    account,
+                    Notification::transfer(Category::Tokens, Direction::Incoming, text),
+                ));
+            }
+            TokenEvent::Burn {
+                owner: Address::Account(account),
+                ..
+            } => {
+                let text = format!("🔥 Tokens burned\n{}", text);
+                notifications.push((
+                    account,
+                    Notification::transfer(Category::Tokens, Direction::Outgoing, text),
+                ));
+            }
+            TokenEvent::UpdateOperator {
+                owner: Address::Account(account),
+                ..
+            } => {
+                let text = format!("🔑 Token operator updated\n{}", text);
+                notifications.push((account, Notification::new(Category::Tokens, text)));
             }
             _ => {}
         }
     }
-    None
+
+    notifications
+}
+
+/// Formats CIS-2 token event details.
+fn format_token_event(event: &TokenEvent) -> String {
+    match event {
+        TokenEvent::Transfer {
+            token_id,
+            amount,
+            from,
+            to,
+        } => format!(
+            "Token ID: <code>{}</code>\nAmount: {}\nFrom: {}\nTo: {}",
+            token_id,
+            amount,
+            format_address(from),
+            format_address(to)
+        ),
+        TokenEvent::Mint {
+            token_id,
+            amount,
+            owner,
+        }
+        | TokenEvent::Burn {
+            token_id,
+            amount,
+            owner,
+        } => format!(
+            "Token ID: <code>{}</code>\nAmount: {}\nOwner: {}",
+            token_id,
+            amount,
+            format_address(owner)
+        ),
+        TokenEvent::UpdateOperator {
+            add,
+            owner,
+            operator,
+        } => format!(
+            "Owner: {}\n{}: {}",
+            format_address(owner),
+            if *add {
+                "Added operator"
+            } else {
+                "Removed operator"
+            },
+            format_address(operator)
+        ),
+        TokenEvent::TokenMetadata { token_id, url } => format!(
+            "Token ID: <code>{}</code>\nMetadata URL: {}",
+            token_id,
+            html::escape(url)
+        ),
+    }
+}
+
+/// Formats contract event logs, decoding CIS-2 token events and truncating long raw ones.
+fn format_logs(logs: &[String]) -> String {
+    logs.iter()
+        .map(|log| {
+            if let Some(event) = cis2::decode(log) {
+                let kind = match event {
+                    TokenEvent::Transfer { .. } => "transfer",
+                    TokenEvent::Mint { .. } => "mint",
+                    TokenEvent::Burn { .. } => "burn",
+                    TokenEvent::UpdateOperator { .. } => "update operator",
+                    TokenEvent::TokenMetadata { .. } => "token metadata",
+                };
+                format!("CIS-2 {}:\n{}\n", kind, format_token_event(&event))
+            } else if log.len() > MAX_LOG_LEN {
+                format!("Log: <code>{}…</code>\n", &log[..MAX_LOG_LEN])
+            } else {
+                format!("Log: <code>{}</code>\n", log)
+            }
+        })
+        .collect()
+}
+
+fn parse_contract_payload(payload: &str) -> (i64, ContractAddress, &str) {
+    let mut parts = payload.splitn(4, '|');
+    let index_id: i64 = parts.next().unwrap().parse().unwrap();
+    let index: u64 = parts.next().unwrap().parse().unwrap();
+    let subindex: u64 = parts.next().unwrap().parse().unwrap();
+    let summary = parts.next().unwrap();
+    (index_id, ContractAddress { index, subindex }, summary)
+}
+
+fn parse_summary_payload(payload: &str) -> (i64, &str) {
+    let mut parts = payload.splitn(2, '|');
+    let id: i64 = parts.next().unwrap().parse().unwrap();
+    let summary = parts.next().unwrap();
+    (id, summary)
+}
+
+fn parse_payload(payload: &str) -> (i64, String, u64, &str) {
+    let mut parts = payload.splitn(4, '|');
+    let index_id: i64 = parts.next().unwrap().parse().unwrap();
+    let address = hex::decode(&parts.next().unwrap()[2..])
+        .unwrap()
+        .to_base58check(1);
+    let block_time: u64 = parts.next().unwrap().parse().unwrap();
+    let summary = parts.next().unwrap();
+    (index_id, address, block_time, summary)
+}
+
+fn format_sender(sender: Option<&AccountAddress>) -> String {
+    if let Some(address) = sender {
+        format!("Sender: {}\n", format_account_address(address, false))
+    } else {
+        String::new()
+    }
+}
+
+/// Formats memo of a transfer, if there is one among the events.
+fn format_memo_line(events: &[Event]) -> String {
+    let memo = events.iter().find_map(|event| match event {
+        Event::TransferMemo { memo } => Some(memo),
+        _ => None,
+    });
+
+    if let Some(memo) = memo {
+        format!("Memo: {}\n", format_memo(memo))
+    } else {
+        String::new()
+    }
+}
+
+/// Formats tag of an unrecognized event or outcome from its raw JSON.
+fn format_raw_tag(value: &serde_json::Value) -> String {
+    let tag = value["tag"].as_str().unwrap_or("without tag");
+    format!("<code>{}</code>", html::escape(tag))
+}
+
+/// Formats the epoch from which a stake change made in block with slot time `block_time`
+/// is effective, `cooldown` is the env variable with the cooldown period in epochs.
+fn format_effective_epoch(block_time: u64, cooldown: &str) -> String {
+    let genesis_time = DateTime::parse_from_rfc3339(&env("CONCORDIUM_GENESIS_TIME")).unwrap();
+    let cooldown: u64 = env(cooldown).parse().unwrap();
+    let epoch = effective_epoch(genesis_time.timestamp_millis() as u64, block_time, cooldown);
+    format!("\nEffective from epoch: {}", epoch)
+}
+
+/// Returns the epoch from which a stake change made at `block_time` is effective,
+/// times are given in milliseconds.
+fn effective_epoch(genesis_time: u64, block_time: u64, cooldown: u64) -> u64 {
+    block_time.saturating_sub(genesis_time) / EPOCH_DURATION + cooldown
+}
+
+fn format_bool(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Formats credential registration IDs, one per line.
+fn format_cred_ids(cred_ids: &[String]) -> String {
+    if cred_ids.is_empty() {
+        return "none".to_string();
+    }
+
+    cred_ids
+        .iter()
+        .map(|id| format!("\n<code>{}</code>", html::escape(id)))
+        .collect()
+}
+
+/// Formats release schedule of a scheduled transfer.
+fn format_schedule(schedule: &AmountWithSchedule) -> String {
+    let mut lines = String::from("Schedule:\n");
+    for (timestamp, amount) in schedule.releases() {
+        lines.push_str(&format!(
+            "• {}: {} CCD\n",
+            format_timestamp(*timestamp),
+            amount
+        ));
+    }
+    lines
+}
+
+/// Stores future releases of a scheduled transfer to account for reminders.
+async fn store_releases(account: &AccountAddress, tx_hash: &str, schedule: &AmountWithSchedule) {
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    let releases: Vec<(u64, Amount)> = schedule
+        .releases()
+        .iter()
+        .filter(|(timestamp, _)| *timestamp > now)
+        .copied()
+        .collect();
+
+    if !releases.is_empty() {
+        if let Err(err) = db::add_scheduled_releases(account, tx_hash, &releases).await {
+            error!("{}", err);
+        }
+    }
+}
+
+fn format_direction(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Incoming => "⬇️ Incoming",
+        Direction::Outgoing => "⬆️ Outgoing",
+        Direction::Both => "🔄 Self",
+    }
+}
+
+fn is_account(address: &Address, account: &AccountAddress) -> bool {
+    match address {
+        Address::Account(address) => *address == *account,
+        _ => false,
+    }
+}
+
+/// Returns direction of a transfer relative to the account, `None` if the account
+/// is neither the sender nor the receiver. Transfer to self is `Both`, so it's notified once.
+fn transfer_direction(from: &Address, to: &Address, account: &AccountAddress) -> Option<Direction> {
+    match (is_account(from, account), is_account(to, account)) {
+        (true, true) => Some(Direction::Both),
+        (false, true) => Some(Direction::Incoming),
+        (true, false) => Some(Direction::Outgoing),
+        (false, false) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// IDs of updates in the index, with gaps left by updates nobody is subscribed to.
+    fn index_ids(last_id: i64) -> Vec<i64> {
+        (1..=last_id).filter(|id| id % 3 != 0).collect()
+    }
+
+    /// Replays the index from `from` like the catch-up does, returns IDs of handled updates.
+    async fn replay(index: &[i64], from: i64, page_size: i64) -> (Vec<i64>, i64) {
+        let handled = RefCell::new(Vec::new());
+        let (count, last_id) = process_pages(
+            from,
+            page_size,
+            |from, limit| {
+                let page: Vec<i64> = index
+                    .iter()
+                    .copied()
+                    .filter(|&id| id >= from)
+                    .take(limit as usize)
+                    .collect();
+                async move { Ok::<_, sqlx::Error>(page) }
+            },
+            |id| {
+                handled.borrow_mut().push(id);
+                async move { id }
+            },
+        )
+        .await
+        .unwrap();
+
+        let handled = handled.into_inner();
+        assert_eq!(count, handled.len());
+        (handled, last_id)
+    }
+
+    #[tokio::test]
+    async fn replays_each_update_once_across_pages() {
+        let index = index_ids(40);
+        for page_size in 1..=30 {
+            let (handled, last_id) = replay(&index, 10, page_size).await;
+            let expected: Vec<i64> = index.iter().copied().filter(|&id| id >= 10).collect();
+            assert_eq!(handled, expected, "page size {}", page_size);
+            assert_eq!(last_id, 40);
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_nothing_after_the_last_update() {
+        let index = index_ids(40);
+        let (handled, last_id) = replay(&index, 41, 4).await;
+        assert!(handled.is_empty());
+        assert_eq!(last_id, 41);
+    }
+
+    #[test]
+    fn nothing_is_handled_without_progress() {
+        assert!(!is_handled(1, None));
+        assert!(is_handled(1, Some(1)));
+        assert!(!is_handled(2, Some(1)));
+    }
+
+    #[test]
+    fn stake_change_is_effective_after_cooldown() {
+        let genesis_time = 1_623_218_400_000;
+        assert_eq!(effective_epoch(genesis_time, genesis_time, 168), 168);
+        assert_eq!(
+            effective_epoch(genesis_time, genesis_time + EPOCH_DURATION - 1, 168),
+            168
+        );
+        assert_eq!(
+            effective_epoch(genesis_time, genesis_time + 10 * EPOCH_DURATION, 168),
+            178
+        );
+    }
+
+    /// Summary of a contract update logging events, `<5,0>` is the updated contract.
+    fn contract_update(logs: Vec<String>) -> BlockSummary {
+        let instigator = AccountAddress::new([0x11; 32].to_base58check(1));
+        BlockSummary::TransactionSummary {
+            sender: Some(instigator.clone()),
+            hash: "00".repeat(32),
+            cost: Amount::from(100),
+            energy_cost: 1000,
+            r#type: TransactionSummaryType::AccountTransaction(Some(TransactionType::Update)),
+            result: TransactionOutcome::Success {
+                events: vec![Event::Updated {
+                    address: ContractAddress {
+                        index: 5,
+                        subindex: 0,
+                    },
+                    instigator: Address::Account(instigator),
+                    amount: Amount::from(0),
+                    receive_name: "token.transfer".into(),
+                    events: logs,
+                }],
+            },
+            index: 0,
+        }
+    }
+
+    #[test]
+    fn token_transfer_to_self_is_notified_once() {
+        let alice = AccountAddress::new([0x11; 32].to_base58check(1));
+        let bob = AccountAddress::new([0x22; 32].to_base58check(1));
+        let (alice_log, bob_log) = (
+            format!("00{}", "11".repeat(32)),
+            format!("00{}", "22".repeat(32)),
+        );
+        let summary = contract_update(vec![
+            format!("ff01aa01{}{}", alice_log, alice_log),
+            format!("ff01aa01{}{}", alice_log, bob_log),
+        ]);
+
+        let contract = ContractAddress {
+            index: 5,
+            subindex: 0,
+        };
+        let notifications: Vec<_> = token_notifications(&summary, &contract)
+            .into_iter()
+            .map(|(account, notification)| (account, notification.direction))
+            .collect();
+        assert_eq!(
+            notifications,
+            vec![
+                (alice.clone(), Some(Direction::Both)),
+                (bob, Some(Direction::Incoming)),
+                (alice, Some(Direction::Outgoing)),
+            ]
+        );
+    }
+
+    #[test]
+    fn delegator_moving_between_pools_is_notified_to_both_owners() {
+        let delegator = AccountAddress::new([0x11; 32].to_base58check(1));
+        let summary = contract_update(Vec::new());
+        let change = |previous, baker_id| PoolChange {
+            delegator_id: 7,
+            delegator: delegator.clone(),
+            previous,
+            baker_id,
+        };
+
+        let notifications = pool_notifications(&summary, vec![change(Some(1), Some(2))]);
+        let pools: Vec<u64> = notifications
+            .iter()
+            .map(|(baker_id, _)| *baker_id)
+            .collect();
+        assert_eq!(pools, vec![1, 2]);
+        assert!(notifications[0].1.text.contains("left your pool"));
+        assert!(notifications[1].1.text.contains("joined your pool"));
+
+        assert!(pool_notifications(&summary, vec![change(Some(2), Some(2))]).is_empty());
+        assert_eq!(
+            pool_notifications(&summary, vec![change(None, Some(2))]).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn transfer_to_self_has_single_direction() {
+        let alice =
+            AccountAddress::new("3kBx2h5Y2veb4hZgAJWPrr8RyQESKm5TjzF3ti1QQ4VSYLwK1G".into());
+        let bob = AccountAddress::new("4hvvPeHb9HY4Lur7eUZv4KfL3tYBug8DRc4X9cVU8mpJLa1V1G".into());
+        let (from, to) = (
+            Address::Account(alice.clone()),
+            Address::Account(bob.clone()),
+        );
+
+        assert_eq!(
+            transfer_direction(&from, &to, &alice),
+            Some(Direction::Outgoing)
+        );
+        assert_eq!(
+            transfer_direction(&from, &to, &bob),
+            Some(Direction::Incoming)
+        );
+        assert_eq!(
+            transfer_direction(&from, &from, &alice),
+            Some(Direction::Both)
+        );
+        assert_eq!(transfer_direction(&from, &from, &bob), None);
+
+        for setting in &Direction::variants() {
+            assert!(setting.accepts(Direction::Both));
+        }
+    }
 }
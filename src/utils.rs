@@ -1,5 +1,9 @@
+use crate::cbor;
 use crate::types::{AccountAddress, Address};
+use chrono::{TimeZone, Utc};
+use std::collections::HashMap;
 use std::fmt;
+use teloxide::utils::html;
 
 const MAINNET_DASHBOARD_URL: &str = "http://dashboard.mainnet.concordium.software";
 const MAINNET_API_URL: &str = "https://wallet-proxy.mainnet.concordium.software/v0";
@@ -22,7 +26,7 @@ pub fn is_mainnet() -> bool {
     }
 }
 
-enum Emoji {
+pub enum Emoji {
     Account,
     Contract,
 }
@@ -37,22 +41,27 @@ impl fmt::Display for Emoji {
     }
 }
 
+/// Tags account or contract address, which is formatted with the recipient's label
+/// or address book name if there is one when the message is stored for them,
+/// see `format_tagged_addresses`.
 pub fn format_address(address: &Address) -> String {
     match address {
         Address::Account(account) => format_account_address(account, true),
-        Address::Contract(contract) => {
-            format!(
-                "{}&lt;{},{}&gt;",
-                Emoji::Contract,
-                contract.index,
-                contract.subindex
-            )
-        }
+        Address::Contract(contract) => AddressTag::Contract.wrap(&contract.to_string()),
     }
 }
 
+/// Tags account address like `format_address`.
 pub fn format_account_address(account: &AccountAddress, with_emoji: bool) -> String {
-    let addr = account.to_string();
+    let tag = if with_emoji {
+        AddressTag::Account
+    } else {
+        AddressTag::PlainAccount
+    };
+    tag.wrap(account.address())
+}
+
+fn format_account_link(addr: &str, text: &str, with_emoji: bool) -> String {
     let url = if is_mainnet() {
         MAINNET_API_URL
     } else {
@@ -65,18 +74,106 @@ pub fn format_account_address(account: &AccountAddress, with_emoji: bool) -> Str
             url,
             addr,
             Emoji::Account,
-            &addr[..8]
+            text
         )
     } else {
-        format!(
-            r#"<a href="{}/accBalance/{}">{}</a>"#,
-            url,
-            addr,
-            &addr[..8]
-        )
+        format!(r#"<a href="{}/accBalance/{}">{}</a>"#, url, addr, text)
+    }
+}
+
+/// Pseudo-tags of addresses in message texts, which are shared by all recipients.
+/// Text from the chain is HTML-escaped, so it can't contain them.
+#[derive(Clone, Copy)]
+enum AddressTag {
+    Account,
+    PlainAccount,
+    /// Contract address formatted as `<index,subindex>`.
+    Contract,
+}
+
+impl AddressTag {
+    const ALL: [AddressTag; 3] = [
+        AddressTag::Account,
+        AddressTag::PlainAccount,
+        AddressTag::Contract,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            AddressTag::Account => "account",
+            AddressTag::PlainAccount => "plain-account",
+            AddressTag::Contract => "contract",
+        }
+    }
+
+    fn wrap(self, address: &str) -> String {
+        format!("<{0}>{1}</{0}>", self.name(), address)
+    }
+
+    /// Formats tagged address showing `label` instead of the address if given.
+    fn format(self, address: &str, label: Option<&str>) -> String {
+        if let AddressTag::Contract = self {
+            return format!(
+                "{}{}",
+                Emoji::Contract,
+                html::escape(label.unwrap_or(address))
+            );
+        }
+
+        let text = match label {
+            Some(label) => html::escape(label),
+            None => address.chars().take(8).collect(),
+        };
+        format_account_link(address, &text, matches!(self, AddressTag::Account))
     }
 }
 
+/// Finds the first tagged address in text.
+/// Returns its start and end positions including the tags, the tag and the address.
+fn find_tagged_address(text: &str) -> Option<(usize, usize, AddressTag, &str)> {
+    let mut first: Option<(usize, usize, AddressTag, &str)> = None;
+    for &tag in &AddressTag::ALL {
+        let open = format!("<{}>", tag.name());
+        let close = format!("</{}>", tag.name());
+        let start = match (text.find(&open), first) {
+            (Some(start), Some((first_start, ..))) if first_start < start => continue,
+            (Some(start), _) => start,
+            (None, _) => continue,
+        };
+        let address_start = start + open.len();
+        if let Some(len) = text[address_start..].find(&close) {
+            let address = &text[address_start..address_start + len];
+            first = Some((start, address_start + len + close.len(), tag, address));
+        }
+    }
+    first
+}
+
+/// Returns addresses tagged in text by address formatters.
+pub fn tagged_addresses(text: &str) -> Vec<&str> {
+    let mut addresses = Vec::new();
+    let mut rest = text;
+    while let Some((_, end, _, address)) = find_tagged_address(rest) {
+        addresses.push(address);
+        rest = &rest[end..];
+    }
+    addresses
+}
+
+/// Formats addresses tagged in text by address formatters for a recipient, `labels` maps
+/// addresses to the recipient's labels or address book names shown instead of them.
+pub fn format_tagged_addresses(text: &str, labels: &HashMap<String, String>) -> String {
+    let mut formatted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((start, end, tag, address)) = find_tagged_address(rest) {
+        formatted.push_str(&rest[..start]);
+        formatted.push_str(&tag.format(address, labels.get(address).map(String::as_str)));
+        rest = &rest[end..];
+    }
+    formatted.push_str(rest);
+    formatted
+}
+
 pub fn format_txhash(hash: &str) -> String {
     let url = if is_mainnet() {
         MAINNET_DASHBOARD_URL
@@ -86,3 +183,139 @@ pub fn format_txhash(hash: &str) -> String {
 
     format!(r#"<a href="{}/lookup/{}">{}</a>"#, url, hash, &hash[..8])
 }
+
+/// Formats timestamp given in milliseconds.
+pub fn format_timestamp(timestamp: u64) -> String {
+    Utc.timestamp_millis(timestamp as i64)
+        .format("%Y-%m-%d %H:%M UTC")
+        .to_string()
+}
+
+/// Formats fraction as a percentage rounded to three decimals without trailing zeros,
+/// e.g. 0.07 as "7%" and 0.125 as "12.5%".
+pub fn format_percentage(fraction: f64) -> String {
+    let percentage = format!("{:.3}", fraction * 100.0);
+    let percentage = percentage.trim_end_matches('0').trim_end_matches('.');
+    format!("{}%", percentage)
+}
+
+/// Formats hex-encoded memo for HTML message.
+/// Memo is decoded as CBOR, with fallback to a hex dump if it's not valid CBOR.
+pub fn format_memo(memo: &str) -> String {
+    let bytes = match hex::decode(memo) {
+        Ok(bytes) => bytes,
+        Err(_) => return html::escape(memo),
+    };
+
+    match cbor::decode(&bytes) {
+        Some(value) => format_cbor(value),
+        None => format!("<code>{}</code>", html::escape(&hex_dump(&bytes))),
+    }
+}
+
+/// Formats hex-encoded data registered on-chain for HTML message.
+/// Data is decoded as CBOR, then as UTF-8 text, with fallback to hex, e.g. for hashes.
+pub fn format_registered_data(data: &str) -> String {
+    let bytes = match hex::decode(data) {
+        Ok(bytes) => bytes,
+        Err(_) => return html::escape(data),
+    };
+
+    if let Some(value) = cbor::decode(&bytes) {
+        return format_cbor(value);
+    }
+
+    match String::from_utf8(bytes) {
+        Ok(text) if is_printable(&text) => html::escape(&text),
+        _ => format!("<code>{}</code>", html::escape(data)),
+    }
+}
+
+/// Formats CBOR value, printable text is shown as is and other values in diagnostic notation,
+/// which escapes control characters.
+fn format_cbor(value: cbor::Value) -> String {
+    match value {
+        cbor::Value::Text(text) if is_printable(&text) => html::escape(&text),
+        value => format!("<code>{}</code>", html::escape(&value.to_string())),
+    }
+}
+
+/// Returns `true` if text has no control characters other than line breaks.
+fn is_printable(text: &str) -> bool {
+    !text.chars().any(|c| c.is_control() && c != '\n')
+}
+
+/// Formats bytes as hex followed by their printable ASCII characters, e.g. `48 69 0a |Hi.|`.
+fn hex_dump(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    format!("{} |{}|", hex.join(" "), ascii)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ContractAddress;
+
+    #[test]
+    fn tagged_addresses_are_formatted_with_labels() {
+        let alice = "3kBx2h5Y2veb4hZgAJWPrr8RyQESKm5TjzF3ti1QQ4VSYLwK1G";
+        let bob = "4hvvPeHb9HY4Lur7eUZv4KfL3tYBug8DRc4X9cVU8mpJLa1V1G";
+        let contract = Address::Contract(ContractAddress {
+            index: 1,
+            subindex: 0,
+        });
+        let text = format!(
+            "From: {}\nTo: {}\nVia: {}, {}\n{}",
+            format_account_address(&AccountAddress::new(alice.to_owned()), true),
+            format_account_address(&AccountAddress::new(bob.to_owned()), false),
+            format_address(&contract),
+            format_address(&contract),
+            html::escape("<account>x</account>")
+        );
+        assert_eq!(tagged_addresses(&text), vec![alice, bob, "<1,0>", "<1,0>"]);
+
+        let mut labels = HashMap::new();
+        labels.insert(bob.to_owned(), "Bob & co".to_owned());
+        assert_eq!(
+            format_tagged_addresses(&text, &labels),
+            format!(
+                "From: {}\nTo: {}\nVia: 📝&lt;1,0&gt;, 📝&lt;1,0&gt;\n&lt;account&gt;x&lt;/account&gt;",
+                format_account_link(alice, &alice[..8], true),
+                format_account_link(bob, "Bob &amp; co", false)
+            )
+        );
+
+        labels.insert("<1,0>".to_owned(), "Market".to_owned());
+        assert!(format_tagged_addresses(&text, &labels).contains("Via: 📝Market, 📝Market\n"));
+    }
+
+    #[test]
+    fn text_with_control_characters_is_escaped() {
+        assert_eq!(format_memo("6948656c6c6f0a3c623e"), "Hello\n&lt;b&gt;");
+        // Text with NUL, which PostgreSQL text can't store
+        assert_eq!(format_memo("6100"), r#"<code>"\0"</code>"#);
+        assert_eq!(format_memo("626100"), r#"<code>"a\0"</code>"#);
+        assert_eq!(format_registered_data("6100"), format_memo("6100"));
+        assert_eq!(format_registered_data("610061"), "<code>610061</code>");
+    }
+
+    #[test]
+    fn percentage_has_no_float_artifacts() {
+        assert_eq!(format_percentage(0.07), "7%");
+        assert_eq!(format_percentage(0.1), "10%");
+        assert_eq!(format_percentage(0.125), "12.5%");
+        assert_eq!(format_percentage(0.00001), "0.001%");
+        assert_eq!(format_percentage(0.0), "0%");
+        assert_eq!(format_percentage(1.0), "100%");
+    }
+}